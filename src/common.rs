@@ -24,6 +24,6 @@ pub use databox::DataBox;
 pub use remove_vec::RemoveVec;
 pub use coords::*;
 pub use tree::*;
-pub use sound_manager::{transition_default, transition_length, SoundManager};
+pub use sound_manager::{transition_default, transition_length, SoundManager, Channel, Ambient, Emitter, EmitterSource};
 pub use resource_handler::ResourceHandler;
 pub type UIResources = ResourceHandler;
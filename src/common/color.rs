@@ -1,7 +1,8 @@
 
 use serde::{Serialize, Deserialize};
+use lazy_static::lazy_static;
 
-#[derive(PartialEq,Eq,Copy,Clone,Serialize,Deserialize,Debug)]
+#[derive(PartialEq,Eq,Hash,Copy,Clone,Serialize,Deserialize,Debug)]
 pub struct Rgb(pub u8,pub u8,pub u8);
 impl From<Rgb> for crossterm::style::Color {
     fn from(i: Rgb) -> Self {
@@ -42,11 +43,131 @@ impl From<Hsv> for Rgb {
     }
 }
 
+/// How many colors the terminal can show
+#[derive(PartialEq,Eq,Copy,Clone,Debug)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    /// Only bold and reverse are used
+    Monochrome,
+}
+
+impl ColorDepth {
+    /// From the color_mode option, where 0 means to guess from the environment
+    pub fn from_option(mode: u8) -> Self {
+        match mode {
+            1 => Self::TrueColor,
+            2 => Self::Ansi256,
+            3 => Self::Ansi16,
+            4 => Self::Monochrome,
+            _ => Self::detect()
+        }
+    }
+
+    /// Terminals cannot be asked, so this goes by the conventional environment variables
+    pub fn detect() -> Self {
+        Self::detect_from(|name| std::env::var(name).unwrap_or_default())
+    }
+
+    /// detect, with var giving the value of an environment variable, or "" if it is not set
+    fn detect_from(var: impl Fn(&str) -> String) -> Self {
+        let term = var("TERM");
+        let colorterm = var("COLORTERM");
+        if !var("NO_COLOR").is_empty() || term == "dumb" {
+            Self::Monochrome
+        } else if colorterm == "truecolor" || colorterm == "24bit" || !var("WT_SESSION").is_empty() {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else if term.is_empty() && cfg!(windows) {
+            // The Windows console has understood 24 bit color since Windows 10
+            Self::TrueColor
+        } else {
+            // Includes the Linux console
+            Self::Ansi16
+        }
+    }
+}
+
+/// The usual values of the xterm palette: 16 system colors,
+/// then a 6x6x6 color cube, then 24 grays.
+/// Terminals are often themed to show the system colors differently.
+pub fn ansi_color(i: u8) -> Rgb {
+    const SYSTEM: [Rgb; 16] = [
+        Rgb(0,0,0), Rgb(205,0,0), Rgb(0,205,0), Rgb(205,205,0),
+        Rgb(0,0,238), Rgb(205,0,205), Rgb(0,205,205), Rgb(229,229,229),
+        Rgb(127,127,127), Rgb(255,0,0), Rgb(0,255,0), Rgb(255,255,0),
+        Rgb(92,92,255), Rgb(255,0,255), Rgb(0,255,255), Rgb(255,255,255),
+    ];
+    const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match i {
+        0..=15 => SYSTEM[i as usize],
+        16..=231 => {
+            let i = i-16;
+            Rgb(CUBE[(i/36) as usize], CUBE[(i/6%6) as usize], CUBE[(i%6) as usize])
+        },
+        _ => {
+            let v = 8+(i-232)*10;
+            Rgb(v,v,v)
+        }
+    }
+}
+
+lazy_static!(
+    static ref PALETTE: Vec<[f64; 3]> = (0..=255).map(|i| ansi_color(i).oklab()).collect();
+);
+
+impl Rgb {
+    /// The color in Oklab, where distances follow how different colors look
+    /// rather than how different their values are.
+    pub fn oklab(self) -> [f64; 3] {
+        let linear = |c: u8| {
+            let c = c as f64/255.;
+            if c <= 0.04045 {c/12.92} else {((c+0.055)/1.055).powf(2.4)}
+        };
+        let (r, g, b) = (linear(self.0), linear(self.1), linear(self.2));
+        let l = (0.4122214708*r + 0.5363325363*g + 0.0514459929*b).cbrt();
+        let m = (0.2119034982*r + 0.6806995451*g + 0.1073969566*b).cbrt();
+        let s = (0.0883024619*r + 0.2817188376*g + 0.6299787005*b).cbrt();
+        [
+            0.2104542553*l + 0.7936177850*m - 0.0040720468*s,
+            1.9779984951*l - 2.4285922050*m + 0.4505937099*s,
+            0.0259040371*l + 0.7827717662*m - 0.8086757660*s,
+        ]
+    }
+
+    /// How light the color looks, from 0 to 1
+    pub fn lightness(self) -> f64 {
+        self.oklab()[0]
+    }
+
+    /// The index of the closest looking color of the palette for depth.
+    /// With 256 colors the system colors are left out, since they may be themed.
+    pub fn to_ansi(self, depth: ColorDepth) -> u8 {
+        let range = match depth {
+            ColorDepth::Ansi16 => 0..16,
+            _ => 16..256
+        };
+        let lab = self.oklab();
+        let distance = |other: &[f64; 3]| {
+            (0..3).map(|i| (lab[i]-other[i]).powi(2)).sum::<f64>()
+        };
+        let mut best = range.start;
+        for i in range {
+            if distance(&PALETTE[i]) < distance(&PALETTE[best]) {
+                best = i;
+            }
+        }
+        return best as u8;
+    }
+}
+
 pub const BLACK: Rgb = Rgb(0,0,0);
 pub const RED: Rgb = Rgb(255,0,0);
 pub const WHITE: Rgb = Rgb(255,255,255);
 
-#[derive(PartialEq,Eq,Clone,Copy,Serialize,Deserialize)]
+#[derive(PartialEq,Eq,Clone,Copy,Serialize,Deserialize,Debug)]
 pub struct Style {
     pub fg: Option<Rgb>,
     pub bg: Option<Rgb>,
@@ -151,3 +272,56 @@ impl TileStyle {
 }
 
 pub const NONETILE: TileStyle = TileStyle {fg: None, bg: None};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn detect_with(vars: &[(&str, &str)]) -> ColorDepth {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        ColorDepth::detect_from(|name| vars.get(name).copied().unwrap_or_default().to_string())
+    }
+
+    #[test]
+    fn palette_colors_map_to_themselves() {
+        for i in 0..16 {
+            assert_eq!(ansi_color(i).to_ansi(ColorDepth::Ansi16), i);
+        }
+        for i in 16..=255 {
+            assert_eq!(ansi_color(i).to_ansi(ColorDepth::Ansi256), i);
+        }
+    }
+
+    #[test]
+    fn grays_go_to_the_gray_ramp() {
+        for value in [30, 60, 100, 128, 190, 230] {
+            let index = Rgb(value, value, value).to_ansi(ColorDepth::Ansi256);
+            assert!(index >= 232, "{} went to {}", value, index);
+        }
+    }
+
+    #[test]
+    fn no_color_wins() {
+        assert_eq!(detect_with(&[("NO_COLOR", "1"), ("COLORTERM", "truecolor"), ("TERM", "xterm-256color")]),
+            ColorDepth::Monochrome);
+        assert_eq!(detect_with(&[("TERM", "dumb")]), ColorDepth::Monochrome);
+        // NO_COLOR only counts when it is not empty
+        assert_eq!(detect_with(&[("NO_COLOR", ""), ("TERM", "xterm-256color")]), ColorDepth::Ansi256);
+    }
+
+    #[test]
+    fn colorterm_gives_true_color() {
+        assert_eq!(detect_with(&[("COLORTERM", "truecolor"), ("TERM", "xterm")]), ColorDepth::TrueColor);
+        assert_eq!(detect_with(&[("COLORTERM", "24bit"), ("TERM", "xterm")]), ColorDepth::TrueColor);
+        assert_eq!(detect_with(&[("WT_SESSION", "1"), ("TERM", "xterm")]), ColorDepth::TrueColor);
+    }
+
+    #[test]
+    fn term_decides_otherwise() {
+        assert_eq!(detect_with(&[("TERM", "xterm-256color")]), ColorDepth::Ansi256);
+        assert_eq!(detect_with(&[("TERM", "screen-256color"), ("COLORTERM", "yes")]), ColorDepth::Ansi256);
+        assert_eq!(detect_with(&[("TERM", "xterm")]), ColorDepth::Ansi16);
+        assert_eq!(detect_with(&[("TERM", "linux")]), ColorDepth::Ansi16);
+    }
+}
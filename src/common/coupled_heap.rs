@@ -179,6 +179,32 @@ impl<Ext: Eq+Hash+Copy, T: Ord> CoupledHeap<Ext, T> {
         return x;
     }
     
+    pub fn contains(&self, e: Ext) -> bool {
+        return self.into.contains_key(&e);
+    }
+
+    /// The items for e, in heap order rather than sorted.
+    pub fn get(&self, e: Ext) -> &[T] {
+        match self.into.get(&e) {
+            Some(i) => &self.interior[*i].v,
+            None => &[],
+        }
+    }
+
+    /// Removes the first item for e that matches.
+    pub fn remove_where<F: FnMut(&T) -> bool>(&mut self, e: Ext, f: F) -> Option<T> {
+        let i = *self.into.get(&e)?;
+        let j = self.interior[i].v.iter().position(f)?;
+        let x = if j == self.interior[i].v.len()-1 {
+            self.interior[i].v.pop().unwrap()
+        } else {
+            remove(&mut self.interior[i].v, j)
+        };
+        self.length -= 1;
+        self.shift(i);
+        return Some(x);
+    }
+
     // You must run fix on the same e after running modify.
     // For the moment, I do not know a way to fix this.
     pub fn modify(&mut self, e: Ext) -> &mut Vec<T> {
@@ -277,3 +303,50 @@ impl<Ext: Eq+Hash+Copy, T: Ord> CoupledHeap<Ext, T> {
     
     pub fn len(&self) -> usize {self.length}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(heap: &mut CoupledHeap<usize, (usize, usize)>) -> Vec<(usize, (usize, usize))> {
+        let mut out = Vec::new();
+        while let Some(x) = heap.pop() {
+            out.push(x);
+        }
+        out
+    }
+
+    #[test]
+    fn remove_where() {
+        let mut heap = CoupledHeap::new();
+        for (e, x) in [(0, (5, 0)), (0, (1, 1)), (1, (3, 2)), (0, (9, 3)), (1, (2, 4))] {
+            heap.push(e, x);
+        }
+        // The front of an entity's heap
+        assert_eq!(heap.remove_where(0, |x| x.1 == 1), Some((1, 1)));
+        // The last item of its vec
+        assert_eq!(heap.remove_where(0, |x| x.1 == 3), Some((9, 3)));
+        // The front of the entity at the top of the whole heap
+        assert_eq!(heap.remove_where(1, |x| x.1 == 4), Some((2, 4)));
+        assert_eq!(heap.remove_where(1, |x| x.1 == 4), None);
+        assert_eq!(heap.remove_where(2, |_| true), None);
+        assert_eq!(heap.len(), 2);
+        assert_eq!(drain(&mut heap), vec![(1, (3, 2)), (0, (5, 0))]);
+    }
+
+    #[test]
+    fn remove_where_keeps_order_across_entities() {
+        let mut heap = CoupledHeap::new();
+        for i in 0..20 {
+            heap.push(i % 3, ((i * 7) % 11, i));
+        }
+        for i in [4, 9, 0, 17] {
+            assert!(heap.remove_where(i % 3, |x| x.1 == i).is_some());
+        }
+        let popped: Vec<(usize, usize)> = drain(&mut heap).into_iter().map(|(_, x)| x).collect();
+        let mut sorted = popped.clone();
+        sorted.sort();
+        assert_eq!(popped, sorted);
+        assert_eq!(popped.len(), 16);
+    }
+}
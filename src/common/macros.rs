@@ -6,6 +6,18 @@ macro_rules! errstr {
     }
 }
 
+/// Looks up a string in the current language at runtime.
+/// Arguments fill in the {} of the string, in order.
+#[macro_export]
+macro_rules! tr {
+    ($key:expr) => {
+        crate::filesystem::translate_key($key)
+    };
+    ($key:expr, $($arg:expr),+) => {
+        crate::filesystem::translate_args($key, &[$($arg.to_string()),+])
+    };
+}
+
 #[macro_export]
 macro_rules! err_plus {
     ($error:expr, $add:expr) => {
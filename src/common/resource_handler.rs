@@ -1,9 +1,9 @@
 use std::path::PathBuf;
 use std::fs;
-use crate::filesystem::{from_json, to_json, to_msgpack, from_msgpack, Paths, DebugStream, Options, Options2};
+use crate::filesystem::{from_json, to_json, to_msgpack, from_msgpack, Paths, DebugStream, Options, Options2, keymap_strings};
 use crate::game::GenerationData;
 use crate::ui::{UI, Candidate, RawKey};
-use crate::err;
+use crate::{err, false_if_err};
 use serde::{Serialize,Deserialize};
 
 // Paths will not change during gameplay.
@@ -53,7 +53,7 @@ impl ResourceHandler {
             Ok(t) => return t,
             Err(e) => {
                 self.debug.write(&e);
-                panic!(crate::translate!(early_choke_fail));
+                panic!("{}", crate::tr!("early_choke_fail"));
             }
         };
     }
@@ -65,7 +65,7 @@ impl ResourceHandler {
                 self.debug.write(&e);
                 ui.stop(self);
                 self.flush_errors();
-                panic!(crate::translate!(choke_fail), e);
+                panic!("{}", crate::tr!("choke_fail", e));
             }
         };
     }
@@ -74,6 +74,11 @@ impl ResourceHandler {
         self.debug.write(err);
     }
 
+    /// For information that is not an error, but is useful when debugging.
+    pub fn debug(&mut self, msg: &str) {
+        self.debug.write(msg);
+    }
+
     // For errors that should not stop the program when they occur
     pub fn eat<T>(&mut self, res: Result<T, String>) -> Option<T> {
         match res {
@@ -113,6 +118,12 @@ impl ResourceHandler {
         return to_json(&options, &path, self)
     }
 
+    pub fn save_keys(&mut self) -> bool {
+        let keys = keymap_strings(&self.options.keys);
+        let path = self.path.keymap.clone();
+        return to_json(&keys, &path, self)
+    }
+
     pub fn sound_file(&self, file: &str) -> PathBuf {
         return self.path.sounds.join(file.to_owned()+".mp3");
     }
@@ -125,6 +136,16 @@ impl ResourceHandler {
         let path = self.path.game_save.join(file_name);
         return from_msgpack(&path, self);
     }
+    /// The size in bytes of a file in the current save folder.
+    pub fn save_size(&self, file_name: &str) -> Option<u64> {
+        return fs::metadata(self.path.game_save.join(file_name)).ok().map(|meta| meta.len());
+    }
+    /// Writes plain text into the current save folder, for files meant to be read by people.
+    pub fn save_text(&mut self, file_name: &str, text: &str) -> bool {
+        let path = self.path.game_save.join(file_name);
+        false_if_err!(fs::write(path, text), self);
+        return true;
+    }
 
     pub fn file_str(&mut self, file: &PathBuf) -> Option<String> {
         self.eat(err!(fs::read_to_string(file)))
@@ -1,7 +1,8 @@
 use crate::common::ResourceHandler;
 use crate::{false_if_err,err};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
+use serde::{Serialize,Deserialize};
 
 pub fn transition_default() -> Tween {
     Tween::default()
@@ -18,10 +19,74 @@ use kira::{
 	sound::static_sound::{StaticSoundHandle, StaticSoundData, StaticSoundSettings},
     sound::streaming::{StreamingSoundHandle, StreamingSoundData, StreamingSoundSettings},
     sound::FromFileError,
+    track::{TrackBuilder, TrackHandle},
     tween::{Value, Tween},
     Volume,
 };
 
+/// The sub-tracks sounds are played on. Each has its own volume in the options,
+/// under the main track, whose volume is the master volume.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    Music,
+    Ambience,
+    Effects,
+}
+impl Channel {
+    const ALL: [Channel; 3] = [Channel::Music, Channel::Ambience, Channel::Effects];
+
+    fn volume(&self, resources: &ResourceHandler) -> u8 {
+        match self {
+            Self::Music => resources.options.music_volume,
+            Self::Ambience => resources.options.ambience_volume,
+            Self::Effects => resources.options.effects_volume,
+        }
+    }
+}
+
+fn full_volume() -> u8 {255}
+
+/// A track looped as background music, such as the ambient music of a map.
+#[derive(Serialize,Deserialize,Clone,PartialEq,Eq,Debug)]
+pub struct Ambient {
+    pub track: String,
+    /// Under the music volume in the options
+    #[serde(default="full_volume")]
+    pub volume: u8,
+}
+
+fn default_radius() -> usize {12}
+
+/// A looping sound heard from a place in the world, quieter the more steps away it is.
+#[derive(Serialize,Deserialize,Clone,PartialEq,Eq,Debug)]
+pub struct Emitter {
+    pub sound: String,
+    #[serde(default="full_volume")]
+    pub volume: u8,
+    // Not heard from further than this many steps away
+    #[serde(default="default_radius")]
+    pub radius: usize,
+}
+
+/// What an emitter's sound is coming from.
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
+pub enum EmitterSource {
+    /// An object without an entity: its map, tile and template,
+    /// and how many objects of the same template come before it on that tile
+    Object(usize, usize, usize, usize),
+    /// An object of an entity, by the entity and its template, which together are unique
+    EntityObject(usize, usize),
+    /// The Emitter an entity carries
+    Entity(usize),
+}
+
+// How long emitters take to follow the player's steps
+const EMITTER_TWEEN: u64 = 150;
+
+fn amplitude(volume: u8) -> f64 {
+    volume as f64 / 255.
+}
+
 enum SoundHandle {
     Static(StaticSoundHandle),
     Streaming(StreamingSoundHandle<FromFileError>)
@@ -39,12 +104,35 @@ impl SoundHandle {
             Self::Streaming(handle) => handle.set_volume(volume, tween),
         }
     }
+    fn set_panning(&mut self, panning: f64, tween: Tween) -> Result<(), kira::CommandError> {
+        match self {
+            Self::Static(handle) => handle.set_panning(panning, tween),
+            Self::Streaming(handle) => handle.set_panning(panning, tween),
+        }
+    }
+    fn set_loop_region(&mut self) -> Result<(), kira::CommandError> {
+        match self {
+            Self::Static(handle) => handle.set_loop_region(..),
+            Self::Streaming(handle) => handle.set_loop_region(..),
+        }
+    }
 }
 
-pub struct SoundManager {
+struct Audio {
     manager: AudioManager,
+    /// Indexed by Channel
+    tracks: Vec<TrackHandle>,
+}
+
+pub struct SoundManager {
+    /// None when there is no audio device, or audio is turned off in the options.
+    /// Sounds are then only written to the debug log, so the game runs the same without them.
+    audio: Option<Audio>,
     static_sounds: HashMap<String, StaticSoundData>,
-    background: Option<SoundHandle>
+    background: Option<SoundHandle>,
+    /// What the background should be, kept while audio is off so it can be started again.
+    playing: Option<Ambient>,
+    emitters: HashMap<EmitterSource, SoundHandle>,
 }
 
 impl SoundManager {
@@ -53,67 +141,166 @@ impl SoundManager {
         for name in preloaded.drain(..) {
             match resources.eat(err!(StaticSoundData::from_file(
                 resources.sound_file(&name),
-                StaticSoundSettings::default().volume(0.)
+                StaticSoundSettings::default()
             ))) {
                 Some(audio) => {static_sounds.insert(name, audio);},
                 None => (),
             };
         }
-        Self {
-            manager: resources.early_choke(err!(AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()))),
+        let mut this = Self {
+            audio: None,
             static_sounds,
-            background: None
+            background: None,
+            playing: None,
+            emitters: HashMap::new(),
+        };
+        this.set_enabled(resources.options.audio, resources);
+        return this;
+    }
+
+    /// Opens or closes the audio device. If it cannot be opened, the game goes on silently.
+    pub fn set_enabled(&mut self, enabled: bool, resources: &mut ResourceHandler) -> bool {
+        if !enabled {
+            // Not stop_background, which would forget what to play when audio is back
+            if let Some(mut sound) = self.background.take() {
+                resources.eat(err!(sound.stop(Tween::default())));
+            }
+            self.emitters.clear();
+            self.audio = None;
+            resources.debug("Audio is turned off; sounds will only be logged");
+            return true;
+        }
+        if self.audio.is_some() {
+            return true;
         }
+        let Some(mut manager) = resources.eat(err!(AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()))) else {
+            resources.debug("No audio device could be opened; sounds will only be logged");
+            return false;
+        };
+        let mut tracks = Vec::with_capacity(Channel::ALL.len());
+        for _ in Channel::ALL {
+            tracks.push(false_if_err!(manager.add_sub_track(TrackBuilder::new()), resources));
+        }
+        self.audio = Some(Audio {manager, tracks});
+        self.set_volumes(resources, Tween::default());
+        if let Some(music) = self.playing.clone() {
+            self.crossfade(&music, resources, Tween::default());
+        }
+        return true;
     }
 
+    /// Plays a sound once, as an effect.
     pub fn play(&mut self, which: &str, resources: &mut ResourceHandler) -> bool {
+        return self.play_on(which, Channel::Effects, 1., 0.5, resources).is_some();
+    }
+
+    /// Plays a sound once, as an effect, at a volume from 0 to 1 and panned from left (0) to right (1).
+    pub fn play_positioned(&mut self, which: &str, volume: f64, panning: f64, resources: &mut ResourceHandler) -> bool {
+        return self.play_on(which, Channel::Effects, volume, panning, resources).is_some();
+    }
+
+    fn play_on(&mut self, which: &str, channel: Channel, volume: f64, panning: f64, resources: &mut ResourceHandler) -> Option<SoundHandle> {
+        let Some(Audio {manager, tracks}) = &mut self.audio else {
+            resources.debug(&format!("Sound {} on {:?}", which, channel));
+            return None;
+        };
+        let track = &tracks[channel as usize];
         match self.static_sounds.get(which) {
             Some(audio) => {
-                false_if_err!(self.manager.play(audio.clone()), resources);
+                let audio = audio.with_modified_settings(|settings| settings.output_destination(track).volume(volume).panning(panning));
+                let sound = resources.eat(err!(manager.play(audio)))?;
+                return Some(SoundHandle::Static(sound));
             },
             None => {
-                let stream = false_if_err!(StreamingSoundData::from_file(
+                let stream = resources.eat(err!(StreamingSoundData::from_file(
                     resources.sound_file(which),
-                    StreamingSoundSettings::default().volume(0.)
-                ), resources);
-                false_if_err!(self.manager.play(stream), resources);
+                    StreamingSoundSettings::default().output_destination(track).volume(volume).panning(panning)
+                )))?;
+                let sound = resources.eat(err!(manager.play(stream)))?;
+                return Some(SoundHandle::Streaming(sound));
             }
         }
-        return true;
     }
 
+    /// Loops a sound on the music track, replacing whatever was playing there.
     pub fn background(&mut self, which: &str, resources: &mut ResourceHandler) -> bool {
         if self.background.is_some() {
             self.stop_background(resources);
         }
-        match self.static_sounds.get(which) {
-            Some(audio) => {
-                let mut sound = false_if_err!(self.manager.play(audio.clone()), resources);
-                false_if_err!(sound.set_volume(resources.options.volume as f64 / 255., Tween::default()), resources);
-                false_if_err!(sound.set_loop_region(..), resources);
-                self.background = Some(SoundHandle::Static(sound));
-            },
-            None => {
-                let stream = false_if_err!(StreamingSoundData::from_file(
-                    resources.sound_file(which),
-                    StreamingSoundSettings::default().volume(0.)
-                ), resources);
-                let mut sound = false_if_err!(self.manager.play(stream), resources);
-                false_if_err!(sound.set_volume(resources.options.volume as f64 / 255., Tween::default()), resources);
-                false_if_err!(sound.set_loop_region(..), resources);
-                self.background = Some(SoundHandle::Streaming(sound));
-            }
+        self.playing = Some(Ambient {track: which.to_string(), volume: full_volume()});
+        let Some(mut sound) = self.play_on(which, Channel::Music, 1., 0.5, resources) else {return false};
+        false_if_err!(sound.set_loop_region(), resources);
+        self.background = Some(sound);
+        return true;
+    }
+
+    /// Fades the background out while the new one fades in over the same tween.
+    pub fn crossfade(&mut self, music: &Ambient, resources: &mut ResourceHandler, tween: Tween) -> bool {
+        if let Some(mut old) = self.background.take() {
+            false_if_err!(old.stop(tween), resources);
         }
+        self.playing = Some(music.clone());
+        let Some(mut sound) = self.play_on(&music.track, Channel::Music, 0., 0.5, resources) else {return false};
+        false_if_err!(sound.set_loop_region(), resources);
+        false_if_err!(sound.set_volume(amplitude(music.volume), tween), resources);
+        self.background = Some(sound);
         return true;
     }
 
+    /// The volume of the background sound itself, under the music volume.
+    /// Used for fades in cutscenes, which should not change effects or ambience.
     pub fn set_background_volume(&mut self, volume: u8, resources: &mut ResourceHandler, tween: Tween) -> bool {
+        if let Some(playing) = &mut self.playing {
+            playing.volume = volume;
+        }
         if let Some(sound) = &mut self.background {
-            false_if_err!(sound.set_volume(volume as f64 / 255., tween), resources);
+            false_if_err!(sound.set_volume(amplitude(volume), tween), resources);
         }
         return false;
     }
 
+    /// Loops the sound of every emitter given on the ambience track, at the volume and panning given,
+    /// and stops the ones that are no longer given.
+    pub fn set_emitters(&mut self, heard: Vec<(EmitterSource, String, f64, f64)>, resources: &mut ResourceHandler) {
+        let tween = transition_length(EMITTER_TWEEN);
+        if self.audio.is_none() {
+            // Otherwise every emitter would be logged on every turn
+            return;
+        }
+        let mut still = HashSet::new();
+        for (source, sound, volume, panning) in heard {
+            still.insert(source);
+            match self.emitters.get_mut(&source) {
+                Some(handle) => {
+                    resources.eat(err!(handle.set_volume(volume, tween)));
+                    resources.eat(err!(handle.set_panning(panning, tween)));
+                },
+                None => if let Some(mut handle) = self.play_on(&sound, Channel::Ambience, volume, panning, resources) {
+                    resources.eat(err!(handle.set_loop_region()));
+                    self.emitters.insert(source, handle);
+                }
+            }
+        }
+        self.emitters.retain(|source, handle| {
+            if still.contains(source) {
+                return true;
+            }
+            resources.eat(err!(handle.stop(tween)));
+            false
+        });
+    }
+
+    /// Brings the master volume and the volume of every channel in line with the options.
+    pub fn set_volumes(&mut self, resources: &mut ResourceHandler, tween: Tween) -> bool {
+        let Some(Audio {manager, tracks}) = &mut self.audio else {return true};
+        false_if_err!(manager.main_track().set_volume(amplitude(resources.options.volume), tween), resources);
+        for channel in Channel::ALL {
+            let volume = amplitude(channel.volume(resources));
+            false_if_err!(tracks[channel as usize].set_volume(volume, tween), resources);
+        }
+        return true;
+    }
+
     pub fn stop_background(&mut self, resources: &mut ResourceHandler) -> bool {
         match &mut self.background {
             Some(sound) => {
@@ -122,6 +309,30 @@ impl SoundManager {
             },
             None => ()
         }
+        self.playing = None;
         return true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameData;
+
+    #[test]
+    fn background_is_remembered_while_audio_is_off() {
+        let mut data = GameData::for_tests("background_is_remembered_while_audio_is_off");
+        let resources = data.resources.as_mut();
+        resources.options.audio = false;
+        let mut sound = SoundManager::new(Vec::new(), resources);
+        sound.background("rain", resources);
+        sound.set_enabled(false, resources);
+        assert_eq!(sound.playing, Some(Ambient {track: "rain".to_string(), volume: full_volume()}));
+        let music = Ambient {track: "rain_inside".to_string(), volume: 100};
+        sound.crossfade(&music, resources, Tween::default());
+        sound.set_background_volume(0, resources, Tween::default());
+        assert_eq!(sound.playing, Some(Ambient {volume: 0, ..music}));
+        sound.stop_background(resources);
+        assert_eq!(sound.playing, None);
+    }
+}
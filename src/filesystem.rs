@@ -2,15 +2,19 @@ mod options;
 mod debug_stream;
 mod json;
 mod paths;
+mod localization;
+mod settings;
 
 pub use json::*;
-pub use options::{Options2, Options};
+pub use options::{Options2, Options, KeyMap, BINDABLE, key_name, keys_of, keymap_strings};
 pub use debug_stream::DebugStream;
 pub use paths::Paths;
+pub use settings::{Setting, SettingKind, SETTINGS, setting, merge_settings};
+pub use localization::{set_language, languages, translate_key, translate_args, FALLBACK_LANGUAGE};
 
 pub fn get_resources() -> Result<(Paths, Options, DebugStream), String> {
     let paths = Paths::new()?;
-    let options = Options::new(&paths)?;
-    let debug = DebugStream::new(&paths)?;
+    let mut debug = DebugStream::new(&paths)?;
+    let options = Options::new(&paths, &mut debug)?;
     return Ok((paths, options, debug));
 }
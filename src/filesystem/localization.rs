@@ -0,0 +1,193 @@
+use super::Paths;
+use crate::{err, errstr};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::fs;
+
+// Keys missing from a language are looked up in this one
+pub const FALLBACK_LANGUAGE: &str = "english";
+
+lazy_static!(
+    static ref ENGLISH: HashMap<String, String> = english();
+    static ref STRINGS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+);
+
+// Pairs each key with its string in translation_english
+macro_rules! english {
+    ($($key:ident),* $(,)?) => {
+        [$((stringify!($key), crate::translate!($key))),*]
+    }
+}
+
+/// The fallback language is compiled in, so it is there even before the options are read
+/// and does not depend on any file being shipped.
+fn english() -> HashMap<String, String> {
+    let mut strings: HashMap<String, String> = english!(
+        loading_text, start_err, early_choke_fail, choke_fail, options_bad_key, main_menu_no_match,
+        main_menu_bad_key, behaviors_bad_key, no_keycode_match, empty_key_string, unknown_modifier,
+        one_character_modifiers, unknown_candidate, setting_ignored, intro_text_1, intro_text_2,
+        intro_text_3, intro_text_4, intro_text_5, intro_text_6, menu_start, menu_load, menu_options,
+        menu_exit, menu_continue, setting_volume, setting_music_volume, setting_ambience_volume,
+        setting_effects_volume, setting_audio, setting_text_speed, setting_synchronized_output,
+        setting_color_mode, setting_language, setting_keys, toggle_off, toggle_on, text_speed_slow,
+        text_speed_medium, text_speed_fast, text_speed_instant, color_mode_automatic,
+        color_mode_true_color, color_mode_256, color_mode_16, color_mode_monochrome, change_keys,
+        keys_help, keys_press, keys_cancelled, keys_unsupported, keys_added, keys_removed,
+        keys_last, keys_conflict, candidate_up, candidate_down, candidate_left, candidate_right,
+        candidate_enter, candidate_exit, candidate_wait, candidate_interact, candidate_get,
+        candidate_tab, candidate_debug,
+    ).into_iter().map(|(key, string)| (key.to_string(), string.to_string())).collect();
+    // Not text, so it is kept one character per byte
+    strings.insert("title_background".to_string(),
+        crate::translate!(title_background).iter().map(|&byte| byte as char).collect());
+    return strings;
+}
+
+/// Every language other than the fallback needs a file.
+fn read_language(paths: &Paths, language: &str) -> Result<HashMap<String, String>, String> {
+    let path = paths.languages.join(language.to_string()+".json");
+    if !path.exists() {
+        return Err(errstr!(format!("there is no file for the language {}", language)));
+    }
+    let f = err!(fs::read_to_string(&path))?;
+    return err!(serde_json::from_str(&f));
+}
+
+/// Switches every string looked up through tr! to another language.
+pub fn set_language(paths: &Paths, language: &str) -> Result<(), String> {
+    let strings = if language == FALLBACK_LANGUAGE {
+        HashMap::new()
+    } else {
+        read_language(paths, language)?
+    };
+    *STRINGS.write().unwrap() = strings;
+    Ok(())
+}
+
+/// The names of every language with a file, in order.
+pub fn languages(paths: &Paths) -> Vec<String> {
+    let mut found = vec![FALLBACK_LANGUAGE.to_string()];
+    if let Ok(entries) = fs::read_dir(&paths.languages) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map_or(false, |ext| ext == "json") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    if name != FALLBACK_LANGUAGE {
+                        found.push(name.to_string());
+                    }
+                }
+            }
+        }
+    }
+    found[1..].sort();
+    return found;
+}
+
+/// Falls back to English, then to the key itself.
+pub fn translate_key(key: &str) -> String {
+    let strings = STRINGS.read().unwrap();
+    match strings.get(key).or_else(|| ENGLISH.get(key)) {
+        Some(string) => string.clone(),
+        None => key.to_string(),
+    }
+}
+
+pub fn translate_args(key: &str, args: &[String]) -> String {
+    return fill(&translate_key(key), args);
+}
+
+/// Replaces {} with the arguments in order, and {0}, {1}... with the argument at that position.
+/// {{ and }} are literal braces.
+fn fill(template: &str, args: &[String]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            },
+            '{' => {
+                let mut index = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    index.push(c);
+                }
+                let position = if index.is_empty() {
+                    next += 1;
+                    Some(next-1)
+                } else {
+                    index.trim().parse::<usize>().ok()
+                };
+                match (closed, position.and_then(|i| args.get(i))) {
+                    (true, Some(arg)) => out.push_str(arg),
+                    _ => {
+                        out.push('{');
+                        out.push_str(&index);
+                        if closed {
+                            out.push('}');
+                        }
+                    }
+                }
+            },
+            _ => out.push(ch),
+        }
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::{SETTINGS, SettingKind};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn fill_in_order() {
+        assert_eq!(fill("{} and {}", &args(&["a", "b"])), "a and b");
+    }
+
+    #[test]
+    fn fill_by_position() {
+        assert_eq!(fill("{1} before {0}", &args(&["a", "b"])), "b before a");
+        assert_eq!(fill("{0}{0}", &args(&["a"])), "aa");
+    }
+
+    #[test]
+    fn fill_escaped_braces() {
+        assert_eq!(fill("{{}} {}", &args(&["a"])), "{} a");
+    }
+
+    #[test]
+    fn fill_leaves_missing_arguments() {
+        assert_eq!(fill("{} {} {5}", &args(&["a"])), "a {} {5}");
+        assert_eq!(fill("{x} {", &args(&["a"])), "{x} {");
+    }
+
+    #[test]
+    fn every_setting_has_english() {
+        for setting in SETTINGS.iter() {
+            if let Some(label) = setting.label {
+                assert!(ENGLISH.contains_key(label), "{}", label);
+            }
+            if let SettingKind::Choice {choices, ..} = setting.kind {
+                for choice in choices {
+                    assert!(ENGLISH.contains_key(*choice), "{}", choice);
+                }
+            }
+        }
+    }
+}
@@ -1,40 +1,70 @@
 use std::path::PathBuf;
-use super::Paths;
+use super::{Paths, DebugStream, set_language, merge_settings, setting};
 use crate::ui::{RawKey, Candidate};
 use std::collections::HashMap;
 use crossterm::event::{KeyModifiers, KeyCode};
 use lazy_static::lazy_static;
 use serde::{Deserialize,Serialize};
+use serde_json::{Value, Map};
 use crate::{err, errstr};
 use std::fs;
 
 lazy_static!(
     static ref KEY_NAMES: HashMap<String, KeyCode> = HashMap::from([
-        (crate::translate!("up").to_string(), KeyCode::Up),
-        (crate::translate!("down").to_string(), KeyCode::Down),
-        (crate::translate!("left").to_string(), KeyCode::Left),
-        (crate::translate!("right").to_string(), KeyCode::Right),
-        (crate::translate!("enter").to_string(), KeyCode::Enter),
-        (crate::translate!("escape").to_string(), KeyCode::Esc),
-        (crate::translate!("tab").to_string(), KeyCode::Tab),
+        ("up".to_string(), KeyCode::Up),
+        ("down".to_string(), KeyCode::Down),
+        ("left".to_string(), KeyCode::Left),
+        ("right".to_string(), KeyCode::Right),
+        ("enter".to_string(), KeyCode::Enter),
+        ("escape".to_string(), KeyCode::Esc),
+        ("tab".to_string(), KeyCode::Tab),
     ]);
 
-    static ref CANDIDATE_NAMES: HashMap<String, Candidate> = HashMap::from([
-        (crate::translate!("up").to_string(), Candidate::Up),
-        (crate::translate!("down").to_string(), Candidate::Down),
-        (crate::translate!("left").to_string(), Candidate::Left),
-        (crate::translate!("right").to_string(), Candidate::Right),
-        (crate::translate!("enter").to_string(), Candidate::Enter),
-        (crate::translate!("exit").to_string(), Candidate::Exit),
-        (crate::translate!("wait").to_string(), Candidate::Wait),
-        (crate::translate!("interact").to_string(), Candidate::Interact),
-        (crate::translate!("get").to_string(), Candidate::Get),
-        (crate::translate!("tab").to_string(), Candidate::Tab),
-    ]);
+    static ref CANDIDATE_NAMES: HashMap<String, Candidate> = BINDABLE.iter()
+        .map(|(name, candidate)| (name.to_string(), *candidate)).collect();
 );
 
+/// Every candidate that can be bound to a key, by its name in keys.json,
+/// in the order they are shown when rebinding keys.
+pub const BINDABLE: [(&str, Candidate); 11] = [
+    ("up", Candidate::Up),
+    ("down", Candidate::Down),
+    ("left", Candidate::Left),
+    ("right", Candidate::Right),
+    ("enter", Candidate::Enter),
+    ("exit", Candidate::Exit),
+    ("wait", Candidate::Wait),
+    ("interact", Candidate::Interact),
+    ("get", Candidate::Get),
+    ("tab", Candidate::Tab),
+    ("debug", Candidate::Debug),
+];
+
+/// Used for any candidate that keys.json leaves without a key, or all of them if it cannot be read.
+const DEFAULT_KEYS: [(&str, &[&str]); 11] = [
+    ("up", &["up", "w"]),
+    ("down", &["down", "s"]),
+    ("left", &["left", "a"]),
+    ("right", &["right", "d"]),
+    ("enter", &["enter"]),
+    ("exit", &["escape"]),
+    ("wait", &["."]),
+    ("interact", &["e"]),
+    ("get", &["g"]),
+    ("tab", &["tab"]),
+    ("debug", &["c+d"]),
+];
+
 pub type KeyMap = HashMap<RawKey, Candidate>;
 
+/// A candidate in keys.json can be given one key or a list of them.
+#[derive(Serialize,Deserialize)]
+#[serde(untagged)]
+enum KeyStrings {
+    One(String),
+    Many(Vec<String>),
+}
+
 fn get_key(key_str: &str) -> Result<RawKey, String> {
     let mut split = key_str.rsplit('+');
     let key_name = split.next();
@@ -47,12 +77,12 @@ fn get_key(key_str: &str) -> Result<RawKey, String> {
                     if let (Some(ch), None) = (chars.next(), chars.next()) {
                         KeyCode::Char(ch)
                     } else {
-                        return Err(errstr!(format!(crate::translate!(no_keycode_match), key_str)))
+                        return Err(errstr!(crate::tr!("no_keycode_match", key_str)))
                     }
                 }
             }
         },
-        None => return Err(errstr!(format!(crate::translate!(empty_key_string), key_str))),
+        None => return Err(errstr!(crate::tr!("empty_key_string", key_str))),
     };
     let mut modifiers = KeyModifiers::NONE;
     for modifier in split {
@@ -62,10 +92,10 @@ fn get_key(key_str: &str) -> Result<RawKey, String> {
                 'c' => modifiers |= KeyModifiers::CONTROL,
                 's' => modifiers |= KeyModifiers::SHIFT,
                 'a' => modifiers |= KeyModifiers::ALT,
-                _ => return Err(errstr!(format!(crate::translate!(unknown_modifier), key_str)))
+                _ => return Err(errstr!(crate::tr!("unknown_modifier", key_str)))
             }
         } else {
-            return Err(errstr!(format!(crate::translate!(one_character_modifiers), key_str)))
+            return Err(errstr!(crate::tr!("one_character_modifiers", key_str)))
         }
     }
     return Ok(RawKey {
@@ -74,32 +104,136 @@ fn get_key(key_str: &str) -> Result<RawKey, String> {
     })
 }
 
-fn get_keymap(path: &PathBuf) -> Result<KeyMap, String> { 
-    let f = err!(fs::read_to_string(&path))?;
-    let mut strings: HashMap<String, String> = err!(serde_json::from_str(&f))?;
-    let mut fin: HashMap<RawKey, Candidate> = HashMap::new();
-    for (candidate, key) in strings.drain() {
-        match CANDIDATE_NAMES.get(&candidate) {
-            Some(cand) => {
-                let key = get_key(&key)?;
-                fin.insert(key, *cand);
-            },
-            None => return Err(errstr!(format!(crate::translate!(unknown_candidate), candidate)))
+/// The inverse of get_key, in the format keys.json uses.
+/// None if the key cannot be written there.
+pub fn key_name(key: &RawKey) -> Option<String> {
+    let mut name = String::new();
+    for (modifier, prefix) in [(KeyModifiers::CONTROL, "c+"), (KeyModifiers::SHIFT, "s+"), (KeyModifiers::ALT, "a+")] {
+        if key.modifiers.contains(modifier) {
+            name.push_str(prefix);
+        }
+    }
+    match KEY_NAMES.iter().find(|(_, code)| **code == key.code) {
+        Some((code_name, _)) => name.push_str(code_name),
+        None => match key.code {
+            KeyCode::Char(ch) => name.push(ch),
+            _ => return None,
+        }
+    }
+    // Some characters, like '+', do not survive the trip
+    match get_key(&name) {
+        Ok(parsed) if parsed == *key => Some(name),
+        _ => None,
+    }
+}
+
+/// The keys bound to each candidate, sorted by name.
+pub fn keys_of(keymap: &KeyMap, candidate: Candidate) -> Vec<String> {
+    let mut keys: Vec<String> = keymap.iter()
+        .filter(|(_, bound)| **bound == candidate)
+        .filter_map(|(key, _)| key_name(key)).collect();
+    keys.sort();
+    return keys;
+}
+
+/// keymap in the format of keys.json.
+pub fn keymap_strings(keymap: &KeyMap) -> HashMap<String, impl Serialize> {
+    let mut fin = HashMap::new();
+    for (name, candidate) in BINDABLE {
+        let mut keys = keys_of(keymap, candidate);
+        match keys.len() {
+            0 => continue,
+            1 => fin.insert(name.to_string(), KeyStrings::One(keys.pop().unwrap())),
+            _ => fin.insert(name.to_string(), KeyStrings::Many(keys)),
+        };
+    }
+    return fin;
+}
+
+/// Anything wrong in keys.json is written to debug and left out.
+fn get_keymap(path: &PathBuf, debug: &mut DebugStream) -> KeyMap {
+    let file = err!(fs::read_to_string(&path))
+        .and_then(|f| err!(serde_json::from_str::<HashMap<String, KeyStrings>>(&f)));
+    let strings = match file {
+        Ok(strings) => strings,
+        Err(e) => {
+            debug.write(&e);
+            HashMap::new()
+        }
+    };
+    let (keymap, errors) = parse_keymap(strings);
+    for e in errors {
+        debug.write(&e);
+    }
+    return keymap;
+}
+
+/// The keys that could be read, with defaults for candidates left without any,
+/// and an error for each key or candidate that could not.
+fn parse_keymap(strings: HashMap<String, KeyStrings>) -> (KeyMap, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut fin: KeyMap = HashMap::new();
+    for (candidate, keys) in strings {
+        let Some(cand) = CANDIDATE_NAMES.get(&candidate) else {
+            errors.push(errstr!(crate::tr!("unknown_candidate", candidate)));
+            continue;
+        };
+        let keys = match keys {
+            KeyStrings::One(key) => vec![key],
+            KeyStrings::Many(keys) => keys,
+        };
+        for key in keys {
+            match get_key(&key) {
+                Ok(key) => {fin.insert(key, *cand);},
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+    for (name, keys) in DEFAULT_KEYS {
+        let candidate = CANDIDATE_NAMES[name];
+        if fin.values().any(|bound| *bound == candidate) {
+            continue;
+        }
+        for key in keys {
+            if let Ok(key) = get_key(key) {
+                fin.entry(key).or_insert(candidate);
+            }
         }
     }
-    return Ok(fin);
+    return (fin, errors);
 }
 
+/// What is stored in options.json. Defaults and limits are in SETTINGS.
 #[derive(Serialize,Deserialize)]
 pub struct Options2 {
     pub volume: u8,
+    pub music_volume: u8,
+    pub ambience_volume: u8,
+    pub effects_volume: u8,
+    pub audio: bool,
     pub text_speed: u8,
+    pub synchronized_output: bool,
+    pub color_mode: u8,
+    pub bridge_depth: u8,
+    pub unload_after: usize,
+    pub max_loaded_maps: usize,
+    pub language: String,
 }
 impl From<&Options> for Options2 {
     fn from(item: &Options) -> Self {
         Self {
             volume: item.volume,
-            text_speed: item.text_speed
+            music_volume: item.music_volume,
+            ambience_volume: item.ambience_volume,
+            effects_volume: item.effects_volume,
+            audio: item.audio,
+            text_speed: item.text_speed,
+            synchronized_output: item.synchronized_output,
+            color_mode: item.color_mode,
+            bridge_depth: item.bridge_depth,
+            unload_after: item.unload_after,
+            max_loaded_maps: item.max_loaded_maps,
+            language: item.language.clone(),
         }
     }
 }
@@ -107,20 +241,129 @@ impl From<&Options> for Options2 {
 #[derive(Clone)]
 pub struct Options {
     pub keys: KeyMap,
+    /// The master volume, which the volume of each channel is under
     pub volume: u8,
+    pub music_volume: u8,
+    pub ambience_volume: u8,
+    pub effects_volume: u8,
+    /// When off, or when no audio device can be opened, sounds are only logged
+    pub audio: bool,
     pub text_speed: u8,
+    /// Asks the terminal to show each frame at once, where it can
+    pub synchronized_output: bool,
+    /// Which colors the terminal is drawn with. 0 guesses from the environment,
+    /// otherwise see ColorDepth::from_option.
+    pub color_mode: u8,
+    /// How many maps away from a newly generated map bridges are connected eagerly,
+    /// so that sight lines through them work before anyone walks through.
+    /// Generators may override this with connect_depth. 0, the default, turns this off.
+    pub bridge_depth: u8,
+    /// Maps not seen or walked through for this much game time are saved and unloaded.
+    pub unload_after: usize,
+    /// If more maps than this are loaded, the least recently used ones are unloaded
+    /// even if they have not timed out. 0 means there is no limit.
+    pub max_loaded_maps: usize,
+    /// The name of a file in the languages folder.
+    pub language: String,
 }
 
 impl Options {
-    pub fn new(paths: &Paths) -> Result<Self, String> {
-        let keymap = get_keymap(&paths.keymap);
-        let f = err!(fs::read_to_string(&paths.options))?;
-        let other: Options2 = err!(serde_json::from_str(&f))?;
-        
-        Ok(Options {
-            keys: keymap?,
+    /// Anything missing or wrong in options.json or keys.json is replaced by its default and written to debug.
+    pub fn new(paths: &Paths, debug: &mut DebugStream) -> Result<Self, String> {
+        let file = err!(fs::read_to_string(&paths.options))
+            .and_then(|f| err!(serde_json::from_str::<Map<String, Value>>(&f)));
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                debug.write(&e);
+                Map::new()
+            }
+        };
+        let (merged, ignored) = merge_settings(&file);
+        // Can only fail if SETTINGS and Options2 disagree
+        let other: Options2 = err!(serde_json::from_value(Value::Object(merged)))?;
+        // Before anything else is reported, so that it is in the right language
+        if let Err(e) = set_language(paths, &other.language) {
+            debug.write(&e);
+        }
+        for field in ignored {
+            debug.write(&crate::tr!("setting_ignored", field));
+        }
+        let keymap = get_keymap(&paths.keymap, debug);
+        Ok(Self::from_parts(keymap, other))
+    }
+
+    fn from_parts(keys: KeyMap, other: Options2) -> Self {
+        Options {
+            keys,
             volume: other.volume,
-            text_speed: if other.text_speed > 3 {3} else {other.text_speed}
-        })
+            music_volume: other.music_volume,
+            ambience_volume: other.ambience_volume,
+            effects_volume: other.effects_volume,
+            audio: other.audio,
+            text_speed: other.text_speed,
+            synchronized_output: other.synchronized_output,
+            color_mode: other.color_mode,
+            bridge_depth: other.bridge_depth,
+            unload_after: other.unload_after,
+            max_loaded_maps: other.max_loaded_maps,
+            language: other.language,
+        }
+    }
+
+    /// A setting by its name in options.json
+    pub fn get(&self, field: &str) -> Value {
+        match serde_json::to_value(Options2::from(self)) {
+            Ok(all) => all[field].clone(),
+            Err(_) => Value::Null,
+        }
+    }
+
+    /// Changes a setting by its name in options.json, if the value fits it.
+    pub fn set(&mut self, field: &str, value: &Value) -> Result<(), String> {
+        let valid = setting(field).and_then(|setting| setting.kind.validate(value))
+            .ok_or(errstr!(crate::tr!("setting_ignored", field)))?;
+        let mut all = err!(serde_json::to_value(Options2::from(&*self)))?;
+        all[field] = valid;
+        let other: Options2 = err!(serde_json::from_value(all))?;
+        *self = Self::from_parts(std::mem::take(&mut self.keys), other);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(entries: &[(&str, &[&str])]) -> HashMap<String, KeyStrings> {
+        entries.iter().map(|(name, keys)| (name.to_string(),
+            KeyStrings::Many(keys.iter().map(|key| key.to_string()).collect()))).collect()
+    }
+
+    #[test]
+    fn missing_keys_json_uses_defaults() {
+        let (keymap, errors) = parse_keymap(HashMap::new());
+        assert!(errors.is_empty());
+        for (name, candidate) in BINDABLE {
+            assert!(!keys_of(&keymap, candidate).is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bad_entries_are_skipped() {
+        let (keymap, errors) = parse_keymap(strings(&[
+            ("up", &["k", "nonsense"]),
+            ("flying", &["f"]),
+        ]));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(keys_of(&keymap, Candidate::Up), vec!["k".to_string()]);
+        assert!(!keymap.contains_key(&get_key("f").unwrap()));
+    }
+
+    #[test]
+    fn defaults_do_not_take_bound_keys() {
+        let (keymap, _) = parse_keymap(strings(&[("exit", &["w"])]));
+        assert_eq!(keymap[&get_key("w").unwrap()], Candidate::Exit);
+        assert_eq!(keys_of(&keymap, Candidate::Up), vec!["up".to_string()]);
     }
 }
@@ -9,6 +9,8 @@ pub struct Paths {
     pub colors: PathBuf,
     pub error_log: PathBuf,
     pub templates: PathBuf,
+    pub dialogue: PathBuf,
+    pub languages: PathBuf,
     pub saves: PathBuf,
     pub temp_save: PathBuf,
     pub sounds: PathBuf,
@@ -29,31 +31,38 @@ impl Paths {
             base_dir.pop();
         }
         base_dir = err!(canonicalize(base_dir))?;
-        let data_dir = base_dir.join(crate::translate!("data"));
+        return Ok(Self::from_data_dir(base_dir.join("data")));
+    }
 
-        let options = data_dir.join(concat!(crate::translate!("options"),".json"));
+    /// Everything is found in data_dir, laid out as it is next to the game
+    pub fn from_data_dir(data_dir: PathBuf) -> Self {
+        let options = data_dir.join("options.json");
 
-        let error_log = data_dir.join(crate::translate!("error_log.txt"));
+        let error_log = data_dir.join("error_log.txt");
 
-        let resources = data_dir.join(crate::translate!("resources"));
-        let colors = resources.join(concat!(crate::translate!("colors"),".json"));
-        let templates = resources.join(concat!(crate::translate!("templates"),".json"));
-        let maps = resources.join(crate::translate!("maps"));
-        let sounds = resources.join(crate::translate!("sounds"));
-        let static_sounds = sounds.join(concat!(crate::translate!("static"),".txt"));
-        let misc = resources.join(crate::translate!("miscellaneous"));
+        let resources = data_dir.join("resources");
+        let colors = resources.join("colors.json");
+        let templates = resources.join("templates.json");
+        let maps = resources.join("maps");
+        let dialogue = resources.join("dialogue");
+        let languages = resources.join("languages");
+        let sounds = resources.join("sounds");
+        let static_sounds = sounds.join("static.txt");
+        let misc = resources.join("miscellaneous");
 
-        let saves = data_dir.join(crate::translate!("saves"));
-        let temp_save = saves.join(crate::translate!("temporary"));
-        let game_save = saves.join(crate::translate!("none"));
+        let saves = data_dir.join("saves");
+        let temp_save = saves.join("temporary");
+        let game_save = saves.join("none");
 
-        let keymap = data_dir.join(concat!(crate::translate!("keys"),".json"));
+        let keymap = data_dir.join("keys.json");
 
-        return Ok(Self {
+        return Self {
             data_dir,
             maps,
             colors,
             templates,
+            dialogue,
+            languages,
             saves,
             temp_save,
             sounds,
@@ -63,6 +72,6 @@ impl Paths {
             error_log,
             game_save,
             options,
-        })
+        }
     }
 }
\ No newline at end of file
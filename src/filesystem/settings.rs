@@ -0,0 +1,197 @@
+use serde_json::{Value, Map};
+use super::FALLBACK_LANGUAGE;
+
+pub enum SettingKind {
+    /// A whole number from min to max, moved by step in the options menu
+    Slider {min: i64, max: i64, step: i64, default: i64},
+    /// Stored as the index of the choice; the choices are translated when shown
+    Choice {choices: &'static [&'static str], default: usize},
+    /// Stored as the name of a file in the languages folder
+    Language,
+    Toggle {default: bool},
+    /// Opens the key rebinding screen. Keys are kept in keys.json, not options.json
+    Keys,
+}
+
+pub struct Setting {
+    /// The name of the entry in options.json
+    pub field: &'static str,
+    /// Settings without a label are not shown in the options menu
+    pub label: Option<&'static str>,
+    pub kind: SettingKind,
+}
+
+/// Every entry of options.json, and every row of the options menu in order.
+/// Adding a setting here also needs a field in Options and Options2 of the same name.
+pub static SETTINGS: [Setting; 13] = [
+    Setting {field: "volume", label: Some("setting_volume"),
+        kind: SettingKind::Slider {min: 0, max: 255, step: 5, default: 255}},
+    Setting {field: "music_volume", label: Some("setting_music_volume"),
+        kind: SettingKind::Slider {min: 0, max: 255, step: 5, default: 255}},
+    Setting {field: "ambience_volume", label: Some("setting_ambience_volume"),
+        kind: SettingKind::Slider {min: 0, max: 255, step: 5, default: 255}},
+    Setting {field: "effects_volume", label: Some("setting_effects_volume"),
+        kind: SettingKind::Slider {min: 0, max: 255, step: 5, default: 255}},
+    Setting {field: "audio", label: Some("setting_audio"), kind: SettingKind::Toggle {default: true}},
+    Setting {field: "text_speed", label: Some("setting_text_speed"),
+        kind: SettingKind::Choice {choices: &["text_speed_slow", "text_speed_medium", "text_speed_fast", "text_speed_instant"], default: 1}},
+    Setting {field: "synchronized_output", label: Some("setting_synchronized_output"),
+        kind: SettingKind::Toggle {default: true}},
+    Setting {field: "color_mode", label: Some("setting_color_mode"),
+        kind: SettingKind::Choice {
+            choices: &["color_mode_automatic", "color_mode_true_color", "color_mode_256",
+                "color_mode_16", "color_mode_monochrome"],
+            default: 0
+        }},
+    Setting {field: "language", label: Some("setting_language"), kind: SettingKind::Language},
+    Setting {field: "keys", label: Some("setting_keys"), kind: SettingKind::Keys},
+    Setting {field: "bridge_depth", label: None,
+        kind: SettingKind::Slider {min: 0, max: 255, step: 1, default: 0}},
+    Setting {field: "unload_after", label: None,
+        kind: SettingKind::Slider {min: 100, max: 100_000, step: 100, default: 400}},
+    Setting {field: "max_loaded_maps", label: None,
+        kind: SettingKind::Slider {min: 0, max: 1024, step: 1, default: 32}},
+];
+
+pub fn setting(field: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.field == field)
+}
+
+impl SettingKind {
+    /// Null for settings not stored in options.json
+    pub fn default(&self) -> Value {
+        match self {
+            Self::Slider {default, ..} => Value::from(*default),
+            Self::Choice {default, ..} => Value::from(*default),
+            Self::Language => Value::from(FALLBACK_LANGUAGE),
+            Self::Toggle {default} => Value::from(*default),
+            Self::Keys => Value::Null,
+        }
+    }
+
+    /// The value as it should be stored, or None if it cannot be.
+    /// Numbers out of range are moved into it.
+    pub fn validate(&self, value: &Value) -> Option<Value> {
+        match self {
+            Self::Slider {min, max, ..} => Some(Value::from(value.as_i64()?.clamp(*min, *max))),
+            Self::Choice {choices, ..} => Some(Value::from((value.as_u64()? as usize).min(choices.len()-1))),
+            Self::Language => value.as_str().map(Value::from),
+            Self::Toggle {..} => value.as_bool().map(Value::from),
+            Self::Keys => None,
+        }
+    }
+
+    /// The value moved one step left or right in the options menu.
+    /// Languages are moved through in the menu itself, since the list is not known here.
+    pub fn shift(&self, value: &Value, right: bool) -> Value {
+        match self {
+            Self::Slider {min, max, step, ..} => {
+                let value = value.as_i64().unwrap_or(*min);
+                let next = if right {value.saturating_add(*step)} else {value.saturating_sub(*step)};
+                Value::from(next.clamp(*min, *max))
+            },
+            Self::Choice {choices, ..} => {
+                let value = value.as_u64().unwrap_or(0) as usize;
+                let next = if right {value+1} else {value.saturating_sub(1)};
+                Value::from(next.min(choices.len()-1))
+            },
+            Self::Toggle {..} => Value::from(!value.as_bool().unwrap_or(false)),
+            _ => value.clone()
+        }
+    }
+}
+
+/// The defaults of every setting, overwritten by each valid entry of file.
+/// Also returns the entries of file which were not used.
+pub fn merge_settings(file: &Map<String, Value>) -> (Map<String, Value>, Vec<String>) {
+    let mut ignored = Vec::new();
+    let mut merged = Map::new();
+    for setting in &SETTINGS {
+        if let SettingKind::Keys = setting.kind {
+            continue;
+        }
+        let value = match file.get(setting.field) {
+            Some(value) => match setting.kind.validate(value) {
+                Some(valid) => valid,
+                None => {
+                    ignored.push(setting.field.to_string());
+                    setting.kind.default()
+                }
+            },
+            None => setting.kind.default(),
+        };
+        merged.insert(setting.field.to_string(), value);
+    }
+    for field in file.keys() {
+        if !merged.contains_key(field) {
+            ignored.push(field.clone());
+        }
+    }
+    return (merged, ignored);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn file(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn validate_clamps_numbers() {
+        let slider = SettingKind::Slider {min: 0, max: 255, step: 5, default: 255};
+        assert_eq!(slider.validate(&json!(300)), Some(json!(255)));
+        assert_eq!(slider.validate(&json!(-4)), Some(json!(0)));
+        assert_eq!(slider.validate(&json!(30)), Some(json!(30)));
+        let choice = SettingKind::Choice {choices: &["a", "b", "c"], default: 0};
+        assert_eq!(choice.validate(&json!(7)), Some(json!(2)));
+    }
+
+    #[test]
+    fn validate_refuses_wrong_types() {
+        let slider = SettingKind::Slider {min: 0, max: 255, step: 5, default: 255};
+        assert_eq!(slider.validate(&json!("loud")), None);
+        assert_eq!(slider.validate(&json!(1.5)), None);
+        let choice = SettingKind::Choice {choices: &["a", "b"], default: 0};
+        assert_eq!(choice.validate(&json!(-1)), None);
+        assert_eq!(SettingKind::Toggle {default: true}.validate(&json!(1)), None);
+        assert_eq!(SettingKind::Language.validate(&json!(false)), None);
+        assert_eq!(SettingKind::Keys.validate(&json!({})), None);
+    }
+
+    #[test]
+    fn merge_fills_in_defaults() {
+        let (merged, ignored) = merge_settings(&Map::new());
+        assert!(ignored.is_empty());
+        for setting in &SETTINGS {
+            match setting.kind {
+                SettingKind::Keys => assert!(!merged.contains_key(setting.field)),
+                _ => assert_eq!(merged[setting.field], setting.kind.default()),
+            }
+        }
+    }
+
+    #[test]
+    fn merge_keeps_valid_entries() {
+        let (merged, ignored) = merge_settings(&file(json!({"volume": 100, "audio": false, "unload_after": 1})));
+        assert!(ignored.is_empty());
+        assert_eq!(merged["volume"], json!(100));
+        assert_eq!(merged["audio"], json!(false));
+        assert_eq!(merged["unload_after"], json!(100));
+    }
+
+    #[test]
+    fn merge_ignores_wrong_types_and_unknown_fields() {
+        let (merged, mut ignored) = merge_settings(&file(json!({
+            "volume": "loud",
+            "keys": {"up": "w"},
+            "brightness": 3,
+        })));
+        ignored.sort();
+        assert_eq!(ignored, vec!["brightness", "keys", "volume"]);
+        assert_eq!(merged["volume"], setting("volume").unwrap().kind.default());
+        assert!(!merged.contains_key("brightness"));
+    }
+}
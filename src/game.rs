@@ -5,14 +5,19 @@ mod ui_handler;
 mod entity_handler;
 mod generation;
 mod identifiers;
+mod flags;
+mod dialogue;
+mod soundscape;
 
 pub use identifiers::*;
 pub use generation::{GenerationData, Generator};
 pub use ui_handler::*;
-pub use map_handler::{los_scan, transform_uitile, Traverser, Map, MapData, EuclidMap};
+pub use map_handler::{los_scan, transform_uitile, Traverser, Map, MapData, MapState, EuclidMap};
 pub use gamedata::GameData;
-pub use entity_handler::Template;
+pub use entity_handler::{Template, TemplateDef};
+pub use flags::{Flags, Condition};
 
 use map_handler::MapHandler;
-use update_handler::{UpdateHandler, Update, Time};
+use update_handler::{UpdateHandler, Update, Time, CatchUp};
 use entity_handler::{EntityHandler, Entity, EntityEnum, Behavior, EntityID, TemplateID};
+use soundscape::Hearing;
@@ -1,11 +1,11 @@
 
 fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut ResourceHandler) -> bool {
-    sound.background(crate::translate!("rain_inside"), resources);
+    sound.background("rain_inside", resources);
     let base_volume = resources.options.volume;
     let [split,text,input] = ui.append_to::<Tabs>(ui.root(),
         ExtTree((false, Aligned::new(WidgetBound {width: 27, height: 3}, (0.5,0.5)).into()), vec![
             ExtTree((true, Split::new(true, false, SplitType::AbsBelow(1)).into()), vec![
-                ExtTree((true, LineScroll::new(crate::translate!(intro_text_1), resources.options.text_speed as usize).finished().into()), vec![]),
+                ExtTree((true, LineScroll::new(&crate::tr!("intro_text_1"), resources.options.text_speed as usize).finished().into()), vec![]),
                 ExtTree((true, TextInput::new(20).into()), vec![])
             ])
         ])
@@ -25,7 +25,7 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     loop {
         let result = ui.poll_from(&input_send_or_return, resources);
         if result == (input, Match::Standard(Candidate::Exit)) {
-            sound.background(crate::translate!("rain"), resources);
+            sound.background("rain", resources);
             ui.remove_child::<Tabs>(ui.root(), -1);
             return false;
         }
@@ -36,7 +36,7 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     sound.set_background_volume(base_volume/6*5, resources, transition_length(1000));
     ui.mut_widget::<TextInput>(input).unwrap().set("");
     ui.mut_widget::<Split>(split).unwrap().set_active(true);
-    ui.mut_widget::<LineScroll>(text).unwrap().change(crate::translate!(intro_text_2));
+    ui.mut_widget::<LineScroll>(text).unwrap().change(&crate::tr!("intro_text_2"));
     ui.poll_from(&text_finish, resources);
     ui.mut_widget::<Split>(split).unwrap().set_active(false);
     loop {
@@ -47,16 +47,16 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     }
     sound.set_background_volume(base_volume/6*4, resources, transition_length(1000));
     let input = ui.flat_replace(input, Nothing {}.into());
-    ui.mut_widget::<LineScroll>(text).unwrap().change(crate::translate!(intro_text_3));
+    ui.mut_widget::<LineScroll>(text).unwrap().change(&crate::tr!("intro_text_3"));
     sound.set_background_volume(base_volume/6*3, resources, transition_length(2000));
     ui.poll_from(&text_finish, resources);
     ui.poll_from(&input_send, resources);
-    ui.mut_widget::<LineScroll>(text).unwrap().change(crate::translate!(intro_text_4));
+    ui.mut_widget::<LineScroll>(text).unwrap().change(&crate::tr!("intro_text_4"));
     ui.poll_from(&text_finish, resources);
     ui.poll_from(&input_send, resources);
     sound.set_background_volume(0, resources, transition_default());
     let text2 = LineScroll::new(
-        crate::translate!(intro_text_5), 
+        &crate::tr!("intro_text_5"), 
         resources.options.text_speed as usize).with_style(Style::from_fg(Rgb(255,0,0)).bold()
     );
     let len = text2.len();
@@ -76,7 +76,7 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     {
     let mut widget = ui.mut_widget::<LineScroll>(text).unwrap();
     widget.set_style(Style::default());
-    widget.change(crate::translate!(intro_text_6));
+    widget.change(&crate::tr!("intro_text_6"));
     }
     ui.poll_from(&text_finish, resources);
     ui.poll_from(&input_send, resources);
@@ -0,0 +1,89 @@
+use super::{GameData, ObjTile, EntityID, Condition, entity_handler::OnInteract};
+use crate::ui::{Candidate, Match, Poll};
+use crate::filesystem::from_json;
+use crate::errstr;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A conversation, read from its own file in the dialogue folder.
+#[derive(Deserialize)]
+pub struct Dialogue {
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueNode {
+    pub text: String,
+    // Run when the node is reached
+    #[serde(default)]
+    pub effect: Option<OnInteract>,
+    // With no choices, the conversation ends after this node
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    // Hidden unless this holds
+    #[serde(default)]
+    pub condition: Option<Condition>,
+    #[serde(default)]
+    pub effect: Option<OnInteract>,
+    // Ends the conversation if not given
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// Runs a conversation with the object being talked to, until it ends or the player exits.
+/// Effects are run as interactions of the interactor with that object.
+pub fn converse(name: &str, object: ObjTile, interactor: EntityID, data: &mut GameData) {
+    let path = data.resources.as_ref().path.dialogue.join(name.to_string()+".json");
+    let dialogue: Dialogue = match from_json(&path, data.resources.as_mut()) {
+        Some(dialogue) => dialogue,
+        None => return,
+    };
+    let text_speed = data.resources.as_ref().options.text_speed as usize;
+    let (window, text, replies) = data.ui.open_conversation(text_speed);
+    let text_finish = Poll::from([(text, Candidate::FinishAnimation), data.ui.exit_key()]);
+    let text_continue = Poll::from([(text, Candidate::Enter), data.ui.exit_key()]);
+    let reply_select = Poll::from([(replies, Candidate::Select), data.ui.exit_key()]);
+    let mut current = Some(dialogue.start.clone());
+    while let Some(node_name) = current.take() {
+        let node = match dialogue.nodes.get(&node_name) {
+            Some(node) => node,
+            None => {
+                data.resources.as_mut().err(&errstr!(format!("The dialogue {} has no node named {}", name, node_name)));
+                break;
+            }
+        };
+        if let Some(effect) = &node.effect {
+            effect.call(object, interactor, data);
+        }
+        let available: Vec<&DialogueChoice> = node.choices.iter()
+            .filter(|choice| choice.condition.as_ref().map_or(true, |condition| condition.check(&data.flags)))
+            .collect();
+        data.ui.show_conversation(text, replies, &node.text, available.iter().map(|choice| choice.text.clone()).collect());
+        if data.ui.poll(&text_finish, data.resources.as_mut()) == data.ui.exit_returned() {
+            break;
+        }
+        if available.is_empty() {
+            data.ui.poll(&text_continue, data.resources.as_mut());
+            break;
+        }
+        data.ui.reply_mode(window, true);
+        match data.ui.poll(&reply_select, data.resources.as_mut()) {
+            (_, Match::Selection1D(i)) if (i as usize) < available.len() => {
+                let choice = available[i as usize];
+                if let Some(effect) = &choice.effect {
+                    effect.call(object, interactor, data);
+                }
+                current = choice.next.clone();
+            },
+            _ => (),
+        }
+        data.ui.reply_mode(window, false);
+    }
+    data.ui.close_conversation();
+}
@@ -1,12 +1,14 @@
-use super::{Traverser, UIHandler, UniqTile, ObjTile, entity_handler::OnInteract, Object, ObjTraverser, UpdateHandler, Update, GameData};
+use super::{Traverser, UIHandler, UniqTile, ObjTile, entity_handler::OnInteract, Object, ObjTraverser, UpdateHandler, Update, CatchUp, Condition, GameData};
 use crate::state_machine::Interrupt;
-use crate::common::{TileStyle, RemoveVec};
+use crate::common::{TileStyle, RemoveVec, Emitter};
 use serde::{Serialize,Deserialize};
 
 mod behaviors;
 pub use behaviors::*;
 mod entities;
 pub use entities::*;
+mod components;
+pub use components::*;
 
 fn no() -> bool {false}
 fn yes() -> bool {true}
@@ -25,15 +27,164 @@ pub struct Template {
     #[serde(default="yes")]
     pub see_through: bool,
     pub description: String,
-    pub on_interact: Option<OnInteract>
+    pub on_interact: Option<OnInteract>,
+    // Run when an entity moves onto or off of the tile
+    #[serde(default)]
+    pub on_enter: Option<OnInteract>,
+    #[serde(default)]
+    pub on_leave: Option<OnInteract>,
+    // Looped for as long as the player can hear it
+    #[serde(default)]
+    pub emits: Option<Emitter>,
+    #[serde(default)]
+    pub sounds: TemplateSounds,
+}
+
+/// Sounds played when something happens to an object, by their names in the sounds folder.
+#[derive(Serialize,Deserialize,Clone,Default)]
+pub struct TemplateSounds {
+    #[serde(default)]
+    pub interact: Option<String>,
+    // When an entity steps onto the object
+    #[serde(default)]
+    pub step: Option<String>,
+    // When the object is removed from the world
+    #[serde(default)]
+    pub destroy: Option<String>,
+}
+
+impl TemplateSounds {
+    // Sounds not given here are taken from the base
+    fn or(self, base: &TemplateSounds) -> TemplateSounds {
+        TemplateSounds {
+            interact: self.interact.or_else(|| base.interact.clone()),
+            step: self.step.or_else(|| base.step.clone()),
+            destroy: self.destroy.or_else(|| base.destroy.clone()),
+        }
+    }
 }
 
 #[derive(Serialize,Deserialize)]
 pub struct Entity {
-    pub hp: Option<u32>,
-    pub speed: usize,
     pub loc: Option<ObjTraverser>,
-    pub contains: Option<Vec<Object>>,
+    // At most one of each kind
+    components: Vec<ComponentEnum>,
+}
+
+impl Entity {
+    pub fn new(loc: Option<ObjTraverser>) -> Self {
+        Self {
+            loc,
+            components: Vec::new(),
+        }
+    }
+
+    pub fn with<T: Into<ComponentEnum>>(mut self, component: T) -> Self {
+        self.insert(component);
+        self
+    }
+
+    /// Adds a component, replacing the one of the same kind if there is one.
+    pub fn insert<T: Into<ComponentEnum>>(&mut self, component: T) {
+        let component = component.into();
+        let kind = std::mem::discriminant(&component);
+        match self.components.iter().position(|c| std::mem::discriminant(c) == kind) {
+            Some(i) => self.components[i] = component,
+            None => self.components.push(component),
+        }
+    }
+
+    pub fn get<T>(&self) -> Option<&T>
+    where for<'a> &'a T: TryFrom<&'a ComponentEnum> {
+        self.components.iter().find_map(|c| <&T>::try_from(c).ok())
+    }
+
+    pub fn get_mut<T>(&mut self) -> Option<&mut T>
+    where for<'a> &'a mut T: TryFrom<&'a mut ComponentEnum> {
+        self.components.iter_mut().find_map(|c| <&mut T>::try_from(c).ok())
+    }
+
+    pub fn has<T>(&self) -> bool
+    where for<'a> &'a T: TryFrom<&'a ComponentEnum> {
+        self.get::<T>().is_some()
+    }
+
+    pub fn speed(&self) -> usize {
+        let speed = self.get::<Speed>().map_or(DEFAULT_SPEED, |speed| speed.0);
+        if self.affected_by(|effect| *effect == Effect::Haste) {
+            return (speed/2).max(1);
+        }
+        speed
+    }
+
+    /// How far the entity can see, if it is limited.
+    pub fn vision_radius(&self) -> Option<usize> {
+        self.get::<StatusEffects>()?.active.iter().filter_map(|active| match active.effect {
+            Effect::Blind {radius} => Some(radius),
+            _ => None,
+        }).min()
+    }
+
+    pub fn affected_by<F: Fn(&Effect) -> bool>(&self, f: F) -> bool {
+        match self.get::<StatusEffects>() {
+            Some(effects) => effects.active.iter().any(|active| f(&active.effect)),
+            None => false,
+        }
+    }
+}
+
+/// A template as written in JSON.
+/// Anything left out is taken from the base template, if there is one.
+#[derive(Deserialize)]
+pub struct TemplateDef {
+    pub name: String,
+    #[serde(default)]
+    pub base: Option<String>,
+    // Only the parts of the style given here replace the base's
+    pub style: Option<TileStyle>,
+    pub passable: Option<bool>,
+    pub see_through: Option<bool>,
+    pub description: Option<String>,
+    pub on_interact: Option<OnInteract>,
+    pub on_enter: Option<OnInteract>,
+    pub on_leave: Option<OnInteract>,
+    pub emits: Option<Emitter>,
+    #[serde(default)]
+    pub sounds: TemplateSounds,
+}
+
+impl TemplateDef {
+    pub fn resolve(self, base: Option<&Template>) -> Result<Template, String> {
+        let style = match (self.style, base) {
+            (Some(mut style), Some(base)) => {
+                style.mod_style(base.style);
+                style
+            },
+            (Some(style), None) => style,
+            (None, Some(base)) => base.style,
+            (None, None) => return Err(format!("The template {} has no style", self.name)),
+        };
+        let description = match (self.description, base) {
+            (Some(description), _) => description,
+            (None, Some(base)) => base.description.clone(),
+            (None, None) => return Err(format!("The template {} has no description", self.name)),
+        };
+        Ok(Template {
+            style,
+            description,
+            passable: self.passable.or(base.map(|b| b.passable)).unwrap_or(true),
+            see_through: self.see_through.or(base.map(|b| b.see_through)).unwrap_or(true),
+            on_interact: self.on_interact.or_else(|| base.and_then(|b| b.on_interact.clone())),
+            on_enter: self.on_enter.or_else(|| base.and_then(|b| b.on_enter.clone())),
+            on_leave: self.on_leave.or_else(|| base.and_then(|b| b.on_leave.clone())),
+            emits: self.emits.or_else(|| base.and_then(|b| b.emits.clone())),
+            sounds: match base {
+                Some(base) => self.sounds.or(&base.sounds),
+                None => self.sounds,
+            },
+            name: self.name,
+        })
+    }
 }
 
 /// Things that we only want to have one of, for the player specifically.
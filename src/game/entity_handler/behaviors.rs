@@ -1,10 +1,19 @@
-use super::{Interrupt, ObjTile, UIHandler, TemplateID, EntityID, Object, Traverser, UniqTile, GameData};
+use super::{Interrupt, ObjTile, UIHandler, TemplateID, EntityID, EntityEnum, Inventory, Health, Effect, ActiveEffect, StatusEffects, Object, Traverser, UniqTile, CatchUp, Condition, GameData};
+use crate::game::map_handler::{DoorTimer, Region};
+use crate::game::{Time, Map, Generator};
 use crate::ui::{WidgetEnum, Match, Candidate, Poll};
+use serde::{Serialize,Deserialize};
+use rand::Rng;
 use crate::{errstr, Id};
+use crate::common::Ambient;
+use crate::game::soundscape::DEFAULT_RADIUS;
 use macros::func_enum;
 
+// Time between ticks of status effects
+const EFFECT_TICK: Time = 50;
+
 fn weird_key(data: &mut GameData) -> Interrupt {
-    data.resources.as_mut().err(&errstr!(crate::translate!(behaviors_bad_key)));
+    data.resources.as_mut().err(&errstr!(crate::tr!("behaviors_bad_key")));
     return Interrupt::AbortError;
 }
 
@@ -13,17 +22,23 @@ fn move_object(from: Traverser, to: Traverser, obj: Object, data: &mut GameData)
         return false;
     }
     data.world.move_object(from.tile, to.tile, obj);
+    data.world.touch(to.tile.map, data.updates.current_time);
     return true;
 }
 
 /// A general interaction function called by all entities.
 fn interact(id: usize, tile: UniqTile, data: &mut GameData) -> bool {
     let mut interaction = None;
+    let mut sounds = Vec::new();
     for obj in data.world.objects_on(tile) {
         let template = data.entities.template(obj.template_id);
         if let Some(func) = &template.on_interact {
             interaction = Some((*obj, template.on_interact.clone().unwrap()));
         }
+        sounds.extend(template.sounds.interact.clone());
+    }
+    for sound in sounds {
+        data.play_at(&sound, tile, u8::MAX, DEFAULT_RADIUS);
     }
     if let Some((obj, func)) = interaction {
         func.call((obj, tile), id, data);
@@ -32,11 +47,81 @@ fn interact(id: usize, tile: UniqTile, data: &mut GameData) -> bool {
     false
 }
 
+/// Plays the step sounds of the objects on a tile, other than the entity's own, and the footstep of its floor.
+fn step_sounds(id: usize, tile: UniqTile, data: &mut GameData) {
+    let mut sounds: Vec<String> = data.world.objects_on(tile).iter()
+        .filter(|obj| obj.entity_id != Some(id))
+        .filter_map(|obj| data.entities.template(obj.template_id).sounds.step.clone())
+        .collect();
+    if let Some(footstep) = data.world.get(tile.map).and_then(|mapdata| mapdata.map.footstep(tile.tile)) {
+        sounds.push(footstep.to_string());
+    }
+    for sound in sounds {
+        data.play_at(&sound, tile, u8::MAX, DEFAULT_RADIUS);
+    }
+}
+
+/// Collects the on_enter or on_leave actions of every object on a tile, other than the entity's own.
+fn step_actions(id: usize, tile: UniqTile, entering: bool, data: &GameData) -> Vec<(Object, OnInteract)> {
+    let mut actions = Vec::new();
+    for obj in data.world.objects_on(tile) {
+        if obj.entity_id == Some(id) {
+            continue;
+        }
+        let template = data.entities.template(obj.template_id);
+        let action = if entering {&template.on_enter} else {&template.on_leave};
+        if let Some(func) = action {
+            actions.push((*obj, func.clone()));
+        }
+    }
+    actions
+}
+
+/// The on_exit actions of regions left and on_enter actions of regions entered by stepping between two tiles,
+/// each with the tile of its region. Only regions with any_entity set act for entities other than the player.
+fn region_actions(id: usize, from: UniqTile, to: UniqTile, data: &GameData) -> Vec<(UniqTile, OnInteract)> {
+    let is_player = data.entities.player_data.created && id == data.entities.player_data.entity;
+    let before = data.world.regions_on(from);
+    let after = data.world.regions_on(to);
+    let mut actions = Vec::new();
+    for (key, region) in before.iter() {
+        if (is_player || region.any_entity) && !after.iter().any(|(other, _)| other == key) {
+            actions.extend(region.on_exit.clone().map(|func| (from, func)));
+        }
+    }
+    for (key, region) in after.iter() {
+        if (is_player || region.any_entity) && !before.iter().any(|(other, _)| other == key) {
+            actions.extend(region.on_enter.clone().map(|func| (to, func)));
+        }
+    }
+    actions
+}
+
+/// Everything that follows an entity getting to a tile, other than the on_leave and on_enter of objects:
+/// the actions of regions left and entered, step sounds, and the bridges and music of a map the player comes into.
+fn arrive_at(id: usize, from: Traverser, to: Traverser, data: &mut GameData) {
+    for (tile, func) in region_actions(id, from.tile, to.tile, data) {
+        func.call(Region::target(tile), id, data);
+    }
+    step_sounds(id, to.tile, data);
+    if from.tile.map != to.tile.map && id == data.entities.player_data.entity {
+        Generator::arrive(to.tile.map, data);
+        data.enter_map_music(to.tile.map);
+    }
+}
+
 // Returns whether the entity moved successfully.
 fn move_entity(id: usize, to: Traverser, data: &mut GameData) -> bool {
     if let Some((obj, from)) = data.entities[id].loc {
         if move_object(from, to, obj, data) {
             data.entities[id].loc = Some((obj, to));
+            for (left, func) in step_actions(id, from.tile, false, data) {
+                func.call((left, from.tile), id, data);
+            }
+            arrive_at(id, from, to, data);
+            for (entered, func) in step_actions(id, to.tile, true, data) {
+                func.call((entered, to.tile), id, data);
+            }
             return true;
         }
     }
@@ -44,22 +129,47 @@ fn move_entity(id: usize, to: Traverser, data: &mut GameData) -> bool {
 }
 
 fn pick_up_object(id: usize, tile: UniqTile, data: &mut GameData) -> Option<Object> {
-    let entity = &mut data.entities[id];
-    if entity.contains.is_none() {
-        return None;
-    }
+    let inventory = data.entities[id].get_mut::<Inventory>()?;
     let objects = data.world.objects_mut(tile)?;
 
     for i in (0..objects.len()).rev() {
         // For now, entities cannot pick up a component of themselves
         if objects[i].entity_id.is_none() || objects[i].entity_id.unwrap() != id {
-            entity.contains.as_mut().unwrap().push(objects.swap_remove(i));
+            inventory.contains.push(objects.swap_remove(i));
             break;
         }
     }
     None
 }
 
+/// Gives an entity a status effect, or lengthens the one it already has.
+pub fn add_effect(id: EntityID, effect: Effect, duration: Time, data: &mut GameData) {
+    let until = data.updates.now()+duration;
+    let entity = &mut data.entities[id];
+    if !entity.has::<StatusEffects>() {
+        entity.insert(StatusEffects::default());
+    }
+    let effects = entity.get_mut::<StatusEffects>().unwrap();
+    // Ticks stop once there are no effects left, so they must be started again
+    let ticking = !effects.active.is_empty();
+    match effects.active.iter_mut().find(|active| active.effect == effect) {
+        Some(active) => active.until = active.until.max(until),
+        None => effects.active.push(ActiveEffect {effect, until}),
+    }
+    if !ticking {
+        data.updates.add_update(EFFECT_TICK, id, Behavior::EffectTick);
+    }
+}
+
+// Confused entities sometimes stumble in another direction.
+fn stumble(id: usize, dir: u8, data: &GameData) -> u8 {
+    let mut rng = rand::thread_rng();
+    if data.entities[id].affected_by(|effect| *effect == Effect::Confused) && rng.gen_bool(0.5) {
+        return rng.gen_range(0..4);
+    }
+    dir
+}
+
 fn redraw_los(id: usize, (window, dim): (Id, (usize,usize)), data: &mut GameData) -> Result<bool, Interrupt> {
     let trav;
     {
@@ -70,10 +180,43 @@ fn redraw_los(id: usize, (window, dim): (Id, (usize,usize)), data: &mut GameData
             return Ok(false)
         }
     }
-    UIHandler::update_los(window, trav, data);
+    let radius = data.entities[id].vision_radius();
+    UIHandler::update_los(window, trav, radius, data);
     Ok(true)
 }
 
+/// Runs `then` if the condition holds, otherwise `otherwise` if there is one.
+#[derive(Clone,Debug,Eq,PartialEq,Hash,Serialize,Deserialize)]
+pub struct Conditional {
+    pub condition: Condition,
+    pub then: Box<OnInteract>,
+    #[serde(default)]
+    pub otherwise: Option<Box<OnInteract>>,
+}
+
+/// A door that switches between two templates, which should differ in passable and see_through.
+/// Both templates should have the same Door as their on_interact.
+#[derive(Clone,Debug,Eq,PartialEq,Hash,Serialize,Deserialize)]
+pub struct DoorData {
+    pub open: String,
+    pub closed: String,
+    // A template the interactor must be carrying to open the door
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub locked_text: Option<String>,
+    // How long the door stays open before closing by itself
+    #[serde(default)]
+    pub auto_close: Option<Time>,
+}
+
+fn carries(id: EntityID, template: TemplateID, data: &GameData) -> bool {
+    match data.entities[id].get::<Inventory>() {
+        Some(inventory) => inventory.contains.iter().any(|obj| obj.template_id == template),
+        None => false,
+    }
+}
+
 func_enum! {
 #[derive(Clone,Debug,Eq,PartialEq,Hash,serde::Serialize,serde::Deserialize)]
 pub enum OnInteract: fn(object: ObjTile, interactor: EntityID, data: &mut GameData) {
@@ -85,6 +228,160 @@ pub enum OnInteract: fn(object: ObjTile, interactor: EntityID, data: &mut GameDa
             }
         }
     }
+
+    fn Door(door: &DoorData, object: ObjTile, interactor: EntityID, data: &mut GameData) {
+        if data.world.find_object(object.1, object.0).is_none() {
+            return;
+        }
+        let (open, closed) = match (data.gen.template_names.get(&door.open), data.gen.template_names.get(&door.closed)) {
+            (Some(open), Some(closed)) => (*open, *closed),
+            _ => {
+                data.resources.as_mut().err(&errstr!(format!("The door templates {} and {} do not both exist", door.open, door.closed)));
+                return;
+            }
+        };
+        if object.0.template_id == open {
+            // Nothing closes on an entity standing in the doorway
+            if data.world.objects_on(object.1).iter().any(|obj| obj.entity_id.is_some()) {
+                return;
+            }
+            if let Some(obj) = data.world.find_object(object.1, object.0) {
+                obj.template_id = closed;
+            }
+            if let Some(mapdata) = data.world.get_mut(object.1.map) {
+                mapdata.closing_doors.retain(|timer| timer.tile != object.1.tile);
+            }
+            return;
+        }
+        if let Some(key) = &door.key {
+            let unlocked = match data.gen.template_names.get(key) {
+                Some(key) => carries(interactor, *key, data),
+                None => false,
+            };
+            if !unlocked {
+                if let Some(text) = &door.locked_text {
+                    data.ui.log(text);
+                }
+                return;
+            }
+        }
+        if let Some(obj) = data.world.find_object(object.1, object.0) {
+            obj.template_id = open;
+        }
+        if let Some(delay) = door.auto_close {
+            let at = data.updates.current_time+delay;
+            if let Some(mapdata) = data.world.get_mut(object.1.map) {
+                mapdata.closing_doors.push(DoorTimer {at, tile: object.1.tile, open, closed});
+            }
+        }
+    }
+
+    // Starts a conversation from the dialogue folder.
+    fn Talk(dialogue_name: &String, object: ObjTile, interactor: EntityID, data: &mut GameData) {
+        crate::game::dialogue::converse(dialogue_name, object, interactor, data);
+    }
+
+    // Gives the interactor a status effect for some time.
+    fn Afflict(affliction: &(Effect, Time), _object: ObjTile, interactor: EntityID, data: &mut GameData) {
+        add_effect(interactor, affliction.0, affliction.1, data);
+    }
+
+    fn Message(text: &String, _object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        data.ui.log(text);
+    }
+
+    // Heard from the object, so it is quieter the further away the player is.
+    fn Sound(sound_name: &String, object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        data.play_at(sound_name, object.1, u8::MAX, DEFAULT_RADIUS);
+    }
+
+    fn Music(music_name: &String, _object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        data.set_music(Ambient {track: music_name.clone(), volume: u8::MAX});
+    }
+
+    // Moves the interactor to a location named in map generation.
+    // Arrives the same way as walking, except that on_enter and on_leave of objects are not run,
+    // so teleport pads cannot bounce between each other forever.
+    fn Teleport(location: &String, _object: ObjTile, interactor: EntityID, data: &mut GameData) {
+        let to = match data.gen.locations.get(location) {
+            Some(tile) => *tile,
+            None => {
+                data.resources.as_mut().err(&errstr!(format!("There is no location named {}", location)));
+                return;
+            }
+        };
+        if !data.ensure_loaded(to.map) {
+            return;
+        }
+        if let Some((obj, from)) = data.entities[interactor].loc {
+            if data.world.move_object(from.tile, to, obj) == to {
+                let to = Traverser::new(to);
+                data.entities[interactor].loc = Some((obj, to));
+                data.world.touch(to.tile.map, data.updates.current_time);
+                arrive_at(interactor, from, to, data);
+                // The player's view is otherwise only redrawn at the end of their own turn
+                if data.entities.player_data.created && interactor == data.entities.player_data.entity {
+                    let widget = data.ui.main_character_view;
+                    if let Some(dim) = data.ui.area_size(widget) {
+                        let _ = redraw_los(interactor, (widget, dim), data);
+                    }
+                }
+            }
+        }
+    }
+
+    // Creates an entity on the object's tile.
+    // There is only ever one player, made when the game starts, so it cannot be spawned.
+    fn Spawn(entity: &EntityEnum, object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        if *entity == EntityEnum::Player {
+            data.resources.as_mut().err(&errstr!("the player cannot be spawned"));
+            return;
+        }
+        entity.call(data, Traverser::new(object.1), &None);
+    }
+
+    fn Remove(object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        // Everything else assumes the player entity is always there
+        if data.entities.player_data.created && object.0.entity_id == Some(data.entities.player_data.entity) {
+            data.resources.as_mut().err(&errstr!("the player's own object cannot be removed"));
+            return;
+        }
+        if data.world.remove_obj(object.1, object.0) {
+            if let Some(sound) = data.entities.template(object.0.template_id).sounds.destroy.clone() {
+                data.play_at(&sound, object.1, u8::MAX, DEFAULT_RADIUS);
+            }
+        }
+        if let Some(id) = object.0.entity_id {
+            data.entities.pack(id, &mut data.updates);
+        }
+    }
+
+    fn Sequence(actions: &Vec<OnInteract>, object: ObjTile, interactor: EntityID, data: &mut GameData) {
+        for action in actions {
+            action.call(object, interactor, data);
+        }
+    }
+
+    fn SetFlag(flag: &String, _object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        data.flags.set(flag, 1);
+    }
+
+    fn ClearFlag(flag: &String, _object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        data.flags.set(flag, 0);
+    }
+
+    // Adds to a counter; a negative amount takes away.
+    fn AddFlag(counter: &(String, i64), _object: ObjTile, _interactor: EntityID, data: &mut GameData) {
+        data.flags.add(&counter.0, counter.1);
+    }
+
+    fn If(condition: &Conditional, object: ObjTile, interactor: EntityID, data: &mut GameData) {
+        if condition.condition.check(&data.flags) {
+            condition.then.call(object, interactor, data);
+        } else if let Some(otherwise) = &condition.otherwise {
+            otherwise.call(object, interactor, data);
+        }
+    }
 }
 }
 
@@ -99,11 +396,32 @@ pub enum Behavior: fn(entity: EntityID, data: &mut GameData) -> Result<(), Inter
         Ok(())
     }
 
+    fn EffectTick(id: usize, data: &mut GameData) -> Result<(), Interrupt> {
+        let now = data.updates.now();
+        let entity = &mut data.entities[id];
+        let mut damage = 0;
+        if let Some(effects) = entity.get_mut::<StatusEffects>() {
+            for active in effects.active.iter() {
+                if let Effect::Poison {damage: amount} = active.effect {
+                    damage += amount;
+                }
+            }
+            effects.active.retain(|active| active.until > now);
+            if !effects.active.is_empty() {
+                data.updates.add_update(EFFECT_TICK, id, Behavior::EffectTick);
+            }
+        }
+        if let Some(health) = entity.get_mut::<Health>() {
+            health.hp = health.hp.saturating_sub(damage);
+        }
+        Ok(())
+    }
+
     fn Player(id: usize, data: &mut GameData) -> Result<(), Interrupt> {
         let entity = &data.entities[id];
         // Adding update must be first (so an error during this update does not softlock)
         // Redrawing must occur before moving
-        data.updates.add_update(entity.speed, id, Behavior::Player);
+        data.updates.add_update(entity.speed(), id, Behavior::Player);
         let here;
         match entity.loc {
             None => {
@@ -112,6 +430,7 @@ pub enum Behavior: fn(entity: EntityID, data: &mut GameData) -> Result<(), Inter
             },
             Some(loc) => here = loc
         }
+        data.update_soundscape();
         let widget = data.ui.main_character_view;
         let poll_direction = Poll::from([
             (widget, Candidate::Up),
@@ -146,6 +465,10 @@ pub enum Behavior: fn(entity: EntityID, data: &mut GameData) -> Result<(), Inter
                 (_, Match::Standard(Candidate::Left))  => {what_todo = Move {dir:2};},
                 (_, Match::Standard(Candidate::Right)) => {what_todo = Move {dir:3};},
                 (_, Match::Standard(Candidate::Wait))  => {what_todo = Nothing;},
+                (_, Match::Standard(Candidate::Debug)) => {
+                    data.export_world_graph();
+                    continue;
+                },
                 (_, Match::Standard(Candidate::Interact)) => {
                     match data.ui.poll(&poll_direction, data.resources.as_mut()) {
                         x if x == data.ui.exit_returned() => return Err(Interrupt::MainMenu),
@@ -160,6 +483,7 @@ pub enum Behavior: fn(entity: EntityID, data: &mut GameData) -> Result<(), Inter
             };
             match what_todo {
                 Move {dir} => {
+                    let dir = stumble(id, dir, data);
                     if let Some(to) = data.travel(here.1, dir) {
                         if move_entity(id, to, data) {
                             break;
@@ -186,3 +510,62 @@ pub enum Behavior: fn(entity: EntityID, data: &mut GameData) -> Result<(), Inter
     }
 }
 }
+
+impl Behavior {
+    /// How an update with this behavior is caught up when its map is loaded again.
+    pub fn catch_up(&self) -> CatchUp {
+        match self {
+            Behavior::PlayerStartingDraw => CatchUp::Summarize,
+            Behavior::Player => CatchUp::Freeze,
+            Behavior::EffectTick => CatchUp::Simulate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Entity, DEFAULT_SPEED};
+
+    #[test]
+    fn spawning_the_player_is_refused() {
+        let mut data = GameData::for_tests("spawning_the_player_is_refused");
+        let object = (Object {entity_id: None, template_id: 0}, UniqTile {map: 0, tile: 0});
+        OnInteract::Spawn(EntityEnum::Player).call(object, 0, &mut data);
+        assert!(!data.entities.player_data.created);
+        assert!(data.world.objects_on(object.1).is_empty());
+    }
+
+    // Runs updates until there are none left, returning how many there were
+    fn run_updates(data: &mut GameData) -> usize {
+        let mut count = 0;
+        while let Some((entity, update)) = data.updates.next() {
+            update.behavior.call(entity, data).unwrap();
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn effects_wear_off_after_their_ticks() {
+        let mut data = GameData::for_tests("effects_wear_off_after_their_ticks");
+        let id = data.entities.make_entity(Entity::new(None).with(Health {hp: 10, max: 10}));
+        add_effect(id, Effect::Poison {damage: 1}, EFFECT_TICK*3, &mut data);
+        assert_eq!(run_updates(&mut data), 3);
+        assert_eq!(data.updates.current_time, EFFECT_TICK*3);
+        assert_eq!(data.entities[id].get::<Health>().unwrap().hp, 7);
+        assert!(data.entities[id].get::<StatusEffects>().unwrap().active.is_empty());
+    }
+
+    #[test]
+    fn adding_an_effect_again_lengthens_it() {
+        let mut data = GameData::for_tests("adding_an_effect_again_lengthens_it");
+        let id = data.entities.make_entity(Entity::new(None));
+        add_effect(id, Effect::Haste, EFFECT_TICK, &mut data);
+        add_effect(id, Effect::Haste, EFFECT_TICK*2, &mut data);
+        assert_eq!(data.entities[id].speed(), DEFAULT_SPEED/2);
+        // Only one tick is running, so the effect ends after two
+        assert_eq!(run_updates(&mut data), 2);
+        assert_eq!(data.entities[id].speed(), DEFAULT_SPEED);
+    }
+}
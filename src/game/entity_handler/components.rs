@@ -0,0 +1,175 @@
+use super::{Object, EntityID};
+use crate::game::Time;
+use serde::{Serialize,Deserialize};
+use serde_json::Value;
+pub use crate::common::Emitter;
+
+// The speed of an entity without a Speed component
+pub const DEFAULT_SPEED: usize = 100;
+
+#[derive(Serialize,Deserialize,Clone)]
+pub struct Health {
+    pub hp: u32,
+    pub max: u32,
+}
+
+/// How long an entity waits between its turns.
+#[derive(Serialize,Deserialize,Clone)]
+pub struct Speed(pub usize);
+
+#[derive(Serialize,Deserialize,Clone,Default)]
+pub struct Inventory {
+    pub contains: Vec<Object>,
+}
+
+#[derive(Serialize,Deserialize,Clone)]
+pub struct Faction {
+    pub name: String,
+}
+
+/// Whatever a behavior needs to remember between updates.
+#[derive(Serialize,Deserialize,Clone,Default)]
+pub struct AIState {
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub target: Option<EntityID>,
+}
+
+#[derive(Serialize,Deserialize,Clone)]
+pub struct Light {
+    pub radius: u32,
+}
+
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,Eq,PartialEq,Hash)]
+pub enum Effect {
+    // Damage every effect tick
+    Poison {damage: u32},
+    // Halves the time between turns
+    Haste,
+    // Nothing further than the radius can be seen
+    Blind {radius: usize},
+    // Moves go in random directions half of the time
+    Confused,
+}
+
+#[derive(Serialize,Deserialize,Clone)]
+pub struct ActiveEffect {
+    pub effect: Effect,
+    pub until: Time,
+}
+
+/// Effects which wear off over time, ticked by Behavior::EffectTick while any remain.
+#[derive(Serialize,Deserialize,Clone,Default)]
+pub struct StatusEffects {
+    pub active: Vec<ActiveEffect>,
+}
+
+macro_rules! components {
+    ($($used:ident),*) => {
+        /// Any one piece of data an entity can carry.
+        /// In JSON, each is written as {"Name": data}.
+        #[derive(Serialize,Deserialize,Clone)]
+        pub enum ComponentEnum {
+            $($used($used)),*
+        }
+
+        $(
+        impl From<$used> for ComponentEnum {
+            fn from(value: $used) -> Self {
+                ComponentEnum:: $used(value)
+            }
+        }
+        impl<'a> TryFrom<&'a ComponentEnum> for &'a $used {
+            type Error = ();
+
+            fn try_from(value: &'a ComponentEnum) -> Result<Self, Self::Error> {
+                match value {
+                    ComponentEnum:: $used(component) => Ok(component),
+                    _ => Err(())
+                }
+            }
+        }
+        impl<'a> TryFrom<&'a mut ComponentEnum> for &'a mut $used {
+            type Error = ();
+
+            fn try_from(value: &'a mut ComponentEnum) -> Result<Self, Self::Error> {
+                match value {
+                    ComponentEnum:: $used(component) => Ok(component),
+                    _ => Err(())
+                }
+            }
+        }
+        )*
+    }
+} components! {
+    Health, Speed, Inventory, Faction, AIState, Light, StatusEffects, Emitter
+}
+
+/// Reads the "components" list out of the entity info given in map generation.
+pub fn components_from_info(info: &Option<Value>) -> Result<Vec<ComponentEnum>, String> {
+    match info.as_ref().and_then(|info| info.get("components")) {
+        Some(list) => serde_json::from_value(list.clone()).map_err(|e| e.to_string()),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Entity;
+    use serde_json::json;
+
+    fn from_info(info: Value) -> Entity {
+        let mut entity = Entity::new(None);
+        for component in components_from_info(&Some(info)).unwrap() {
+            entity.insert(component);
+        }
+        entity
+    }
+
+    #[test]
+    fn components_come_from_entity_info() {
+        let entity = from_info(json!({"components": [
+            {"Health": {"hp": 5, "max": 10}},
+            {"Speed": 50},
+            {"Faction": {"name": "rats"}},
+            {"AIState": {}},
+        ]}));
+        let health = entity.get::<Health>().unwrap();
+        assert_eq!((health.hp, health.max), (5, 10));
+        assert_eq!(entity.get::<Speed>().unwrap().0, 50);
+        assert_eq!(entity.get::<Faction>().unwrap().name, "rats");
+        assert_eq!(entity.get::<AIState>().unwrap().target, None);
+        assert!(!entity.has::<Inventory>());
+    }
+
+    #[test]
+    fn components_survive_saving() {
+        let entity = from_info(json!({"components": [
+            {"Light": {"radius": 3}},
+            {"StatusEffects": {"active": [{"effect": "Haste", "until": 40}]}},
+        ]}));
+        let saved = rmp_serde::encode::to_vec(&entity).unwrap();
+        let loaded: Entity = rmp_serde::decode::from_slice(&saved).unwrap();
+        assert_eq!(loaded.get::<Light>().unwrap().radius, 3);
+        let effects = &loaded.get::<StatusEffects>().unwrap().active;
+        assert_eq!((effects[0].effect, effects[0].until), (Effect::Haste, 40));
+    }
+
+    #[test]
+    fn inserting_replaces_the_same_kind() {
+        let mut entity = from_info(json!({"components": [{"Speed": 50}]}));
+        entity.insert(Speed(20));
+        entity.get_mut::<Speed>().unwrap().0 += 1;
+        assert_eq!(entity.get::<Speed>().unwrap().0, 21);
+        assert_eq!(entity.speed(), 21);
+    }
+
+    #[test]
+    fn entity_info_without_components() {
+        assert!(components_from_info(&None).unwrap().is_empty());
+        assert!(components_from_info(&Some(json!({"name": "rat"}))).unwrap().is_empty());
+        assert!(components_from_info(&Some(json!({"components": [{"Wings": 2}]}))).is_err());
+    }
+}
@@ -1,4 +1,5 @@
-use super::{GameData, Traverser, Object, Entity};
+use super::{GameData, Traverser, Object, Entity, Health, Speed, Inventory, components_from_info};
+use crate::errstr;
 use super::behaviors::*;
 use macros::func_enum;
 use serde_json::Value;
@@ -14,6 +15,7 @@ pub enum EntityEnum: fn(data: &mut GameData, trav: Traverser, info: &Option<Valu
         }
         data.entities.player_data.created = true;
         let id = data.entities.next_id();
+        data.entities.player_data.entity = id;
         // Creating the object
         let obj = Object {
             entity_id: Some(id), template_id: *data.gen.template_names.get("player").unwrap(),
@@ -21,12 +23,16 @@ pub enum EntityEnum: fn(data: &mut GameData, trav: Traverser, info: &Option<Valu
         data.world.create_obj(trav.tile, obj);
 
         // Creating the entity
-        let entity = Entity {
-            hp: Some(100),
-            speed: 100,
-            contains: Some(Vec::new()),
-            loc: Some((obj, trav)),
-        };
+        let mut entity = Entity::new(Some((obj, trav)))
+            .with(Health {hp: 100, max: 100})
+            .with(Speed(100))
+            .with(Inventory::default());
+        match components_from_info(info) {
+            Ok(components) => for component in components {
+                entity.insert(component);
+            },
+            Err(e) => data.resources.as_mut().err(&errstr!(e)),
+        }
         let _entity_id = data.entities.make_entity(entity);
         
         // Adding an update
@@ -0,0 +1,152 @@
+use serde::{Serialize,Deserialize};
+use std::collections::HashMap;
+
+/// Story progress: named flags and counters, saved with the game.
+/// A flag is a counter which is either zero (unset) or not.
+#[derive(Serialize,Deserialize,Default)]
+pub struct Flags {
+    values: HashMap<String, i64>,
+}
+
+impl Flags {
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> i64 {
+        return *self.values.get(name).unwrap_or(&0);
+    }
+
+    pub fn is_set(&self, name: &str) -> bool {
+        return self.get(name) != 0;
+    }
+
+    pub fn set(&mut self, name: &str, value: i64) {
+        if value == 0 {
+            self.values.remove(name);
+        } else {
+            self.values.insert(name.to_string(), value);
+        }
+    }
+
+    /// Counters stop at the largest and smallest i64 rather than wrapping.
+    pub fn add(&mut self, name: &str, amount: i64) {
+        self.set(name, self.get(name).saturating_add(amount));
+    }
+}
+
+/// A check against the flags, for use in JSON.
+#[derive(Clone,Debug,Eq,PartialEq,Hash,Serialize,Deserialize)]
+pub enum Condition {
+    Set(String),
+    Unset(String),
+    AtLeast(String, i64),
+    Below(String, i64),
+    Equals(String, i64),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn check(&self, flags: &Flags) -> bool {
+        use Condition::*;
+        match self {
+            Set(name) => flags.is_set(name),
+            Unset(name) => !flags.is_set(name),
+            AtLeast(name, x) => flags.get(name) >= *x,
+            Below(name, x) => flags.get(name) < *x,
+            Equals(name, x) => flags.get(name) == *x,
+            All(conditions) => conditions.iter().all(|c| c.check(flags)),
+            Any(conditions) => conditions.iter().any(|c| c.check(flags)),
+            Not(condition) => !condition.check(flags),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Condition::*;
+
+    fn flags(values: &[(&str, i64)]) -> Flags {
+        let mut flags = Flags::new();
+        for (name, value) in values {
+            flags.set(name, *value);
+        }
+        flags
+    }
+
+    #[test]
+    fn set_and_clear() {
+        let mut flags = Flags::new();
+        assert!(!flags.is_set("door"));
+        flags.set("door", 1);
+        assert!(flags.is_set("door"));
+        assert_eq!(flags.get("door"), 1);
+        flags.set("door", 0);
+        assert!(!flags.is_set("door"));
+        assert!(flags.values.is_empty());
+    }
+
+    #[test]
+    fn add_counts_up_and_down() {
+        let mut flags = Flags::new();
+        flags.add("coins", 3);
+        flags.add("coins", 4);
+        assert_eq!(flags.get("coins"), 7);
+        flags.add("coins", -7);
+        assert!(!flags.is_set("coins"));
+        assert!(flags.values.is_empty());
+        flags.add("debt", -2);
+        assert_eq!(flags.get("debt"), -2);
+    }
+
+    #[test]
+    fn add_saturates() {
+        let mut flags = flags(&[("big", i64::MAX-1), ("small", i64::MIN+1)]);
+        flags.add("big", 5);
+        flags.add("small", -5);
+        assert_eq!(flags.get("big"), i64::MAX);
+        assert_eq!(flags.get("small"), i64::MIN);
+    }
+
+    #[test]
+    fn set_and_unset_conditions() {
+        let flags = flags(&[("met", 1)]);
+        assert!(Set("met".to_string()).check(&flags));
+        assert!(!Set("left".to_string()).check(&flags));
+        assert!(Unset("left".to_string()).check(&flags));
+        assert!(!Unset("met".to_string()).check(&flags));
+    }
+
+    #[test]
+    fn number_conditions() {
+        let flags = flags(&[("coins", 5)]);
+        assert!(AtLeast("coins".to_string(), 5).check(&flags));
+        assert!(!AtLeast("coins".to_string(), 6).check(&flags));
+        assert!(Below("coins".to_string(), 6).check(&flags));
+        assert!(!Below("coins".to_string(), 5).check(&flags));
+        assert!(Equals("coins".to_string(), 5).check(&flags));
+        assert!(!Equals("coins".to_string(), 4).check(&flags));
+        // Missing counters are 0
+        assert!(Equals("gems".to_string(), 0).check(&flags));
+    }
+
+    #[test]
+    fn combined_conditions() {
+        let flags = flags(&[("met", 1)]);
+        let yes = || Set("met".to_string());
+        let no = || Set("left".to_string());
+        assert!(All(vec![yes(), yes()]).check(&flags));
+        assert!(!All(vec![yes(), no()]).check(&flags));
+        assert!(All(vec![]).check(&flags));
+        assert!(Any(vec![no(), yes()]).check(&flags));
+        assert!(!Any(vec![no(), no()]).check(&flags));
+        assert!(!Any(vec![]).check(&flags));
+        assert!(Not(Box::new(no())).check(&flags));
+        assert!(!Not(Box::new(yes())).check(&flags));
+    }
+}
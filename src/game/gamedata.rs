@@ -1,12 +1,61 @@
 
 use crate::state_machine::Interrupt;
-use crate::common::{SoundManager, ResourceHandler, TakeBox};
-use crate::ui::UI;
+use crate::common::{SoundManager, ResourceHandler, TakeBox, Ambient, Tree, transition_length};
+use crate::ui::{UI, WidgetData};
 use super::{Generator, Traverser, EntityEnum, Map, MapData, UIHandler, UpdateHandler, EntityHandler, ThroughResult,
-            UniqTile, MapID, Update, Entity, ToTile, MapHandler};
+            Time, UniqTile, MapID, MapState, Update, Entity, ToTile, MapHandler, Flags, Hearing};
 use uuid::Uuid;
 use crate::err;
 use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use serde::de::IgnoredAny;
+
+type MapFile = (Box<MapData>, Vec<(usize, Box<Entity>, Vec<Update>)>);
+
+/// What is written to current_state.save, in the order of its fields.
+#[derive(Serialize)]
+struct SaveState<'a> {
+    widgets: &'a Tree<WidgetData>,
+    world: &'a MapHandler,
+    updates: &'a UpdateHandler,
+    gen: &'a Generator,
+    entities: &'a EntityHandler,
+    ui: &'a UIHandler,
+    flags: &'a Flags,
+    music: &'a Option<Ambient>,
+}
+
+/// The same as SaveState, as it is read back.
+#[derive(Deserialize)]
+struct LoadedState {
+    widgets: Tree<WidgetData>,
+    world: MapHandler,
+    updates: UpdateHandler,
+    gen: Generator,
+    entities: EntityHandler,
+    ui: UIHandler,
+    #[serde(default)]
+    flags: Flags,
+    #[serde(default)]
+    music: Option<Ambient>,
+}
+
+/// The parts of current_state.save needed to draw the world graph; the rest is skipped over
+#[derive(Deserialize)]
+struct WorldSave {
+    _widgets: IgnoredAny,
+    world: MapHandler,
+    _updates: IgnoredAny,
+    gen: Generator,
+    _entities: IgnoredAny,
+    _ui: IgnoredAny,
+    _flags: IgnoredAny,
+    _music: IgnoredAny,
+}
+
+// Played when neither the save nor the player's map says otherwise
+const DEFAULT_MUSIC: &str = "mus_test";
+const MUSIC_FADE: u64 = 2000;
 
 pub struct GameData {
     pub save_id: Uuid,
@@ -17,7 +66,12 @@ pub struct GameData {
     pub resources: TakeBox<ResourceHandler>,
     pub ui: UIHandler,
     pub sound: TakeBox<SoundManager>,
-    pub gen: Generator
+    pub gen: Generator,
+    pub flags: Flags,
+    // The background music, kept so that it can be saved
+    pub music: Option<Ambient>,
+    // How far each tile is from the player, kept until the player moves or their next turn
+    pub hearing: Option<Hearing>,
 }
 
 impl GameData {
@@ -29,6 +83,9 @@ impl GameData {
             entities: EntityHandler::new(),
             updates: UpdateHandler::new(),
             gen: Generator::new(),
+            flags: Flags::new(),
+            music: None,
+            hearing: None,
             ui: UIHandler::new(ui),
             sound: TakeBox::newb(sound),
             resources: TakeBox::newb(resources),
@@ -38,16 +95,17 @@ impl GameData {
         // RESOURCE HANDLER:
         let res = this.resources.as_mut().attach_to_game(&this.save_id.to_string());
         this.resources.as_mut().choke(err!(res), this.ui.writable.as_mut());
-        // SOUND:
-        this.sound.as_mut().background("mus_test", this.resources.as_mut());
         // MAP GENERATION:
         Generator::load_base_data(&mut this);
         let v = Generator::make("start", &mut this);
-        let map_id = this.resources.as_mut().choke(v.ok_or("the starting map could not be created".to_string()), this.ui.writable.as_mut());
+        let start = v.and_then(|map_id| this.world.get(map_id).map(|mapdata| UniqTile { map: map_id, tile: mapdata.map.random_tile() }));
+        let start = this.resources.as_mut().choke(start.ok_or("the starting map could not be created".to_string()), this.ui.writable.as_mut());
         if !this.entities.player_data.created {
-            let traverser = Traverser::new(UniqTile { map: map_id, tile: this.world[map_id].map.random_tile() });
+            let traverser = Traverser::new(start);
             EntityEnum::Player.call(&mut this, traverser, &None);
         }
+        // SOUND:
+        this.start_music();
         return this;
     }
 
@@ -59,20 +117,24 @@ impl GameData {
             entities: EntityHandler::new(),
             updates: UpdateHandler::new(),
             gen: Generator::new(),
+            flags: Flags::new(),
+            music: None,
+            hearing: None,
             ui: UIHandler::new(ui),
             sound: TakeBox::newb(sound),
             resources: TakeBox::newb(resources),
         };
         // UI:
         this.ui.initial_setup();
-        // SOUND:
-        this.sound.as_mut().background("mus_test", this.resources.as_mut());
         // MAP GENERATION:
         Generator::load_base_data(&mut this);
         let v = Generator::start_branch(maps, &mut this);
-        let map_id = this.resources.as_mut().choke(v.ok_or("the starting map could not be created".to_string()), this.ui.writable.as_mut());
-        let traverser = Traverser::new(UniqTile { map: map_id, tile: this.world[map_id].map.random_tile() });
+        let start = v.and_then(|map_id| this.world.get(map_id).map(|mapdata| UniqTile { map: map_id, tile: mapdata.map.random_tile() }));
+        let start = this.resources.as_mut().choke(start.ok_or("the starting map could not be created".to_string()), this.ui.writable.as_mut());
+        let traverser = Traverser::new(start);
         EntityEnum::Player.call(&mut this, traverser, &None);
+        // SOUND:
+        this.start_music();
         return this;
     }
 
@@ -84,6 +146,9 @@ impl GameData {
             entities: EntityHandler::new(),
             updates: UpdateHandler::new(),
             gen: Generator::new(),
+            flags: Flags::new(),
+            music: None,
+            hearing: None,
             // Does not set up new windows; we will be loading them in.
             ui: UIHandler::new(ui),
             sound: TakeBox::newb(sound),
@@ -96,7 +161,7 @@ impl GameData {
         this.load_state();
         this.restore_ui_context();
         // SOUND:
-        this.sound.as_mut().background("mus_test", this.resources.as_mut());
+        this.start_music();
         // RELOADING COLORS AND RENUMBERING TEMPLATES:
         Generator::load_base_data(&mut this);
         return this;
@@ -139,17 +204,12 @@ impl GameData {
         if !self.world.tile_exists(tile) || !self.world.passable(tile) {
             return None;
         }
-        self.world[tile.map].last_access = self.updates.current_time;
+        self.world.touch(tile.map, self.updates.current_time);
         let first = self.world.through(tile,gate);
         match first {
             ThroughResult::Exists(to) => return Some(to),
             ThroughResult::Load(map_id) => {
-                match self.resources.as_mut().load(&(map_id.to_string()+".map")) {
-                    Some(x) => {
-                        self.load_map(map_id, x);
-                    },
-                    None => self.world.invalidate(map_id) // TODO: something
-                }
+                self.ensure_loaded(map_id);
             },
             ThroughResult::Generate(bridge_id) => {
                 Generator::expand(tile.map, bridge_id, self);
@@ -163,6 +223,36 @@ impl GameData {
         }
     }
 
+    /// Loads a map from its file if it has been unloaded.
+    /// Returns whether the map is loaded afterwards; maps which fail
+    /// to load are marked invalid.
+    pub fn ensure_loaded(&mut self, map_id: MapID) -> bool {
+        match self.world.state(map_id) {
+            MapState::Loaded => return true,
+            MapState::Unloaded => (),
+            _ => return false,
+        }
+        let file_name = map_id.to_string()+".map";
+        match self.resources.as_mut().load::<MapFile>(&file_name) {
+            Some(x) => {
+                self.load_map(map_id, x);
+                self.world.touch(map_id, self.updates.current_time);
+                let size = self.resources.as_ref().save_size(&file_name).unwrap_or(0);
+                self.world.stats.loads += 1;
+                self.world.stats.bytes_loaded += size;
+                let summary = format!("loaded map {} ({} bytes); {}", map_id, size, self.world.stats.summary());
+                self.resources.as_mut().debug(&summary);
+                return true;
+            },
+            None => {
+                // Invalid maps are never loaded again, so this is only reported once
+                self.resources.as_mut().err(&crate::errstr!(format!("map {} could not be loaded, so it now leads to the void", map_id)));
+                self.world.invalidate(map_id);
+                return false;
+            }
+        }
+    }
+
     pub fn object_passable(&self, tile: UniqTile) -> bool {
         for obj in self.world.objects_on(tile) {
             if !self.entities.template(obj.template_id).passable {
@@ -181,12 +271,13 @@ impl GameData {
         true
     }
 
-    fn load_map(&mut self, map_id: usize, (mut map, mut entities): (Box<MapData>, Vec<(usize, Box<Entity>, Vec<Update>)>)) {
+    fn load_map(&mut self, map_id: usize, (mut map, mut entities): MapFile) {
         let mut fucking_god_make_it_stop = HashMap::new();
+        let since = map.unloaded_at.take().unwrap_or(self.updates.current_time);
         for (old_id, entity, updates) in entities.drain(..) {
             let new_id = self.entities.make_entity(*entity);
             fucking_god_make_it_stop.insert(old_id, new_id);
-            self.updates.insert(new_id, updates);
+            self.updates.insert_caught_up(new_id, updates, since);
         }
         for obj_list in map.objects.values_mut() {
             for obj in obj_list.iter_mut() {
@@ -216,6 +307,7 @@ impl GameData {
             },
             None => return Err(Interrupt::ForcedExit),
         };
+        self.world.close_doors(self.updates.current_time);
         self.drop_maps();
         Ok(())
     }
@@ -224,7 +316,11 @@ impl GameData {
         if !self.persistent {
             return;
         }
-        let mapdata = self.world.take(map);
+        let mut mapdata = match self.world.take(map) {
+            Some(mapdata) => mapdata,
+            None => return,
+        };
+        mapdata.unloaded_at = Some(self.updates.current_time);
         let mut entities = Vec::new();
         for obj_list in mapdata.objects.values() {
             for obj in obj_list.iter() {
@@ -237,20 +333,38 @@ impl GameData {
                 }
             }
         }
-        self.resources.as_mut().save(&(map.to_string()+".map"), &(mapdata, entities));
+        let file_name = map.to_string()+".map";
+        self.resources.as_mut().save(&file_name, &(mapdata, entities));
+        let size = self.resources.as_ref().save_size(&file_name).unwrap_or(0);
+        self.world.stats.unloads += 1;
+        self.world.stats.bytes_saved += size;
+        let summary = format!("unloaded map {} ({} bytes); {}", map, size, self.world.stats.summary());
+        self.resources.as_mut().debug(&summary);
     }
     
+    /// Unloads maps which have not been used for options.unload_after,
+    /// then the least recently used maps until no more than options.max_loaded_maps are loaded.
+    /// Maps in the current line of sight and the void are never unloaded.
     fn drop_maps(&mut self) {
         if !self.persistent {
             return;
         }
-        let mut next = self.world.nonref_iter(0);
-        while next.is_some() {
-            let (i, mapdata) = next.unwrap();
-            if self.updates.current_time > mapdata.last_access+400 {
+        let unload_after = self.resources.as_ref().options.unload_after;
+        let max_loaded = self.resources.as_ref().options.max_loaded_maps;
+        let mut by_age: Vec<(Time, MapID)> = self.world.iter()
+            .filter(|(i, _)| *i != 0 && !self.world.is_visible(*i))
+            .map(|(i, mapdata)| (mapdata.last_access, i))
+            .collect();
+        by_age.sort();
+        let loaded = self.world.loaded_count();
+        let mut over_budget = if max_loaded > 0 && loaded > max_loaded {loaded-max_loaded} else {0};
+        for (last_access, i) in by_age {
+            if over_budget > 0 || self.updates.current_time > last_access+unload_after {
                 self.drop_map(i);
+                over_budget = over_budget.saturating_sub(1);
+            } else {
+                break;
             }
-            next = self.world.nonref_iter(i+1);
         }
     }
 
@@ -260,36 +374,99 @@ impl GameData {
         }
         // Remove the UI from the UIHandler; we don't want to save it.
         let ui = self.ui.writable.take();
-        let widgets = ui.get_context_widgets(1);
-        let ui_handler = &self.ui;
-        let loaded_maps = &self.world;
-        let updates = &self.updates;
-        let generation = &self.gen;
-        let entities = &self.entities;
-        self.resources.as_mut().save("current_state.save", &(widgets, loaded_maps, updates, generation, entities, ui_handler));
+        let state = SaveState {
+            widgets: ui.get_context_widgets(1),
+            world: &self.world,
+            updates: &self.updates,
+            gen: &self.gen,
+            entities: &self.entities,
+            ui: &self.ui,
+            flags: &self.flags,
+            music: &self.music,
+        };
+        self.resources.as_mut().save("current_state.save", &state);
         self.ui.writable.replace(ui)
     }
 
     pub fn load_state(&mut self) {
         // Resources should already be inside the correct save folder
         let loaded = self.resources.as_mut().load("current_state.save");
-        let (widgets, 
-             loaded_maps, 
-             updates,
-             generation,
-             entities, 
-             ui_handler) = self.resources.as_mut().choke(loaded.ok_or("For some reason, this file could not load.".to_string()), self.ui.writable.as_mut());
-        self.world = loaded_maps;
-        self.updates = updates;
-        self.gen = generation;
-        self.entities = entities;
+        let state: LoadedState = self.resources.as_mut().choke(loaded.ok_or("For some reason, this file could not load.".to_string()), self.ui.writable.as_mut());
+        self.world = state.world;
+        self.updates = state.updates;
+        self.gen = state.gen;
+        self.entities = state.entities;
+        self.flags = state.flags;
+        self.music = state.music;
         let ui = self.ui.writable.take();
-        self.ui = ui_handler;
+        self.ui = state.ui;
         self.ui.writable.replace(ui);
-        self.ui.writable.as_mut().replace_context_2(1, widgets);
+        self.ui.writable.as_mut().replace_context_2(1, state.widgets);
+        // Nothing the player could see may be unloaded before line of sight is scanned again
+        let mut visible = self.ui.drawn_maps();
+        if let Some(player) = self.player_traverser() {
+            visible.insert(player.tile.map);
+        }
+        self.world.set_visible(visible, self.updates.current_time);
+    }
+    
+    /// Writes the graph of the current world to world.dot in the save folder.
+    pub fn export_world_graph(&mut self) -> bool {
+        let resources = self.resources.as_mut();
+        let dot = self.gen.world_dot(&self.world, |map_id| {
+            resources.load::<MapFile>(&(map_id.to_string()+".map")).map(|(map, _)| map)
+        });
+        return resources.save_text("world.dot", &dot);
+    }
+
+    /// The same as export_world_graph, but reads the world out of a save folder
+    /// without starting a game. Resources should already be inside the save folder.
+    pub fn world_graph_from_save(resources: &mut ResourceHandler) -> Option<String> {
+        let WorldSave {world, gen, ..} = resources.load("current_state.save")?;
+        return Some(gen.world_dot(&world, |map_id| {
+            resources.load::<MapFile>(&(map_id.to_string()+".map")).map(|(map, _)| map)
+        }));
     }
     
+    /// Plays the music that was playing when the game was saved,
+    /// or else the music of the player's map.
+    fn start_music(&mut self) {
+        let music = self.music.take()
+            .or_else(|| self.player_map().and_then(|map| self.world.get(map)).and_then(|mapdata| mapdata.music.clone()))
+            .unwrap_or(Ambient {track: DEFAULT_MUSIC.to_string(), volume: u8::MAX});
+        self.sound.as_mut().crossfade(&music, self.resources.as_mut(), transition_length(0));
+        self.music = Some(music);
+    }
+
+    fn player_map(&self) -> Option<MapID> {
+        self.player_traverser().map(|trav| trav.tile.map)
+    }
+
+    /// Crossfades to the music of a map, if it has any.
+    pub fn enter_map_music(&mut self, map: MapID) {
+        if let Some(music) = self.world.get(map).and_then(|mapdata| mapdata.music.clone()) {
+            self.set_music(music);
+        }
+    }
+
+    /// Crossfades to other music. Only the volume changes if the track is already playing.
+    pub fn set_music(&mut self, music: Ambient) {
+        let tween = transition_length(MUSIC_FADE);
+        match &self.music {
+            Some(current) if *current == music => return,
+            Some(current) if current.track == music.track => {
+                self.sound.as_mut().set_background_volume(music.volume, self.resources.as_mut(), tween);
+            },
+            _ => {
+                self.sound.as_mut().crossfade(&music, self.resources.as_mut(), tween);
+            }
+        }
+        self.music = Some(music);
+    }
+
     pub fn take(&mut self) -> (Box<ResourceHandler>, Box<UI>, Box<SoundManager>) {
+        // Nothing in the world can be heard from outside the game
+        self.sound.as_mut().set_emitters(Vec::new(), self.resources.as_mut());
         return (self.resources.take(), self.ui.writable.take(), self.sound.take());
     }
     pub fn replace(&mut self, res: Box<ResourceHandler>, ui: Box<UI>, sound: Box<SoundManager>) {
@@ -302,3 +479,31 @@ impl GameData {
         self.ui.writable.as_mut().set_context(1);
     }
 }
+
+#[cfg(test)]
+impl GameData {
+    /// An empty game without a terminal or audio. Errors are written to a folder of the same name
+    /// in the temporary directory.
+    pub fn for_tests(name: &str) -> GameData {
+        use crate::filesystem::{Paths, DebugStream, Options};
+        let dir = std::env::temp_dir().join("rain_tests").join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = Paths::from_data_dir(dir);
+        let mut debug = DebugStream::new(&paths).unwrap();
+        let options = Options::new(&paths, &mut debug).unwrap();
+        GameData {
+            save_id: Uuid::new_v4(),
+            persistent: false,
+            world: MapHandler::new(),
+            entities: EntityHandler::new(),
+            updates: UpdateHandler::new(),
+            gen: Generator::new(),
+            flags: Flags::new(),
+            music: None,
+            hearing: None,
+            ui: UIHandler::detached(),
+            sound: TakeBox::none(),
+            resources: TakeBox::new(ResourceHandler::new(paths, options, debug)),
+        }
+    }
+}
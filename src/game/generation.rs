@@ -1,4 +1,4 @@
-use super::{GameData, MapData, Map, UniqTile, Traverser, EntityEnum, Object, Template, map_handler::{Bridge, SparseMap, EuclidMap}};
+use super::{GameData, MapData, Map, UniqTile, Traverser, EntityEnum, Object, TemplateDef, ThroughResult, map_handler::{Bridge, SparseMap, EuclidMap, Region}};
 use serde::{Serialize,Deserialize};
 use crate::filesystem::from_json;
 use crate::common::Array2D;
@@ -6,11 +6,12 @@ use crate::errstr;
 use std::sync::OnceLock;
 use uuid::Uuid;
 
-use std::mem::replace;
-use std::collections::HashMap;
+use std::mem::{replace, take};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 mod structs;
 mod color_generation;
+mod graph;
 pub use structs::*;
 
 // Ironically, it turns out making a static global variable
@@ -31,7 +32,21 @@ pub struct Generator {
     /// This variable stores generations which are just meant to
     /// connect multiple maps together without generating world material
     /// on its own.
-    pub generation_without_map: Vec<UsedByGeneration>
+    pub generation_without_map: Vec<UsedByGeneration>,
+    /// Named tiles from map generation, which can be teleported to.
+    #[serde(default)]
+    pub locations: HashMap<String, UniqTile>,
+    /// Maps made since bridges were last connected around new maps.
+    #[serde(skip)]
+    unconnected: Vec<usize>,
+    /// Generators whose own templates have been added, so that making
+    /// another map with one does not warn about its templates again.
+    #[serde(default)]
+    template_files: HashSet<String>,
+    /// Maps made while connecting around other maps.
+    /// Their bridges are connected when the player first arrives on them.
+    #[serde(default)]
+    pending: HashSet<usize>,
 }
 
 impl Generator {
@@ -40,7 +55,11 @@ impl Generator {
             template_names: HashMap::new(),
             // TODO: Unshittify this
             generation: vec!(UsedByGeneration::default()),
-            generation_without_map: Vec::new()
+            generation_without_map: Vec::new(),
+            locations: HashMap::new(),
+            unconnected: Vec::new(),
+            pending: HashSet::new(),
+            template_files: HashSet::new(),
         }
     }
 
@@ -55,15 +74,55 @@ impl Generator {
         let a = from_json::<HashMap<String, ColorType>>(&data.resources.as_ref().path.colors.clone(), data.resources.as_mut());
         COLORS.set(data.choke(a.ok_or(errstr!("los datos JSON para colores son inválidos"))));
         
-        let b = from_json::<Vec<Template>>(&data.resources.as_ref().path.templates.clone(), data.resources.as_mut());
-        let mut templates = data.choke(b.ok_or(errstr!("los datos JSON para plantillas son inválidos")));
-        for template in templates.drain(..) {
-            if !data.gen.template_names.contains_key(&template.name) {
-                let name = template.name.to_string();
-                let id = data.entities.add_template(template);
-                data.gen.template_names.insert(name, id);
+        let b = from_json::<Vec<TemplateDef>>(&data.resources.as_ref().path.templates.clone(), data.resources.as_mut());
+        let templates = data.choke(b.ok_or(errstr!("los datos JSON para plantillas son inválidos")));
+        // Templates already here came from a save, and the objects in it were made with them.
+        Self::add_templates(templates, true, data);
+    }
+
+    /// Adds templates, each after its base.
+    /// The first template with a name is the one that is kept; later ones are ignored,
+    /// with a warning unless `quiet_existing` and the first came before this call.
+    pub fn add_templates(mut defs: Vec<TemplateDef>, quiet_existing: bool, data: &mut GameData) {
+        let mut added = HashSet::new();
+        loop {
+            let before = defs.len();
+            let mut waiting = Vec::new();
+            for def in defs.drain(..) {
+                if let Some(&existing) = data.gen.template_names.get(&def.name) {
+                    if !(quiet_existing && !added.contains(&existing)) {
+                        data.resources.as_mut().debug(&format!("The template {} is defined more than once; only the first is used", def.name));
+                    }
+                    continue;
+                }
+                let base = match &def.base {
+                    Some(base_name) => match data.gen.template_names.get(base_name) {
+                        Some(base_id) => Some(data.entities.template(*base_id)),
+                        None => {
+                            waiting.push(def);
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                match def.resolve(base) {
+                    Ok(template) => {
+                        let name = template.name.to_string();
+                        let id = data.entities.add_template(template);
+                        data.gen.template_names.insert(name, id);
+                        added.insert(id);
+                    },
+                    Err(e) => data.resources.as_mut().err(&errstr!(e)),
+                }
+            }
+            defs = waiting;
+            if defs.len() == 0 || defs.len() == before {
+                break;
             }
         }
+        for def in defs {
+            data.resources.as_mut().err(&errstr!(format!("The base of the template {} does not exist", def.name)));
+        }
     }
 
     fn used<'a>(&'a self, id: usize) -> &'a UsedByGeneration {
@@ -126,6 +185,10 @@ impl Generator {
     }
 
     fn connect(map1: usize, bridge_id1: usize, map2: usize, bridge_id2: usize, flip: bool, data: &mut GameData) {
+        // The other side may have been generated long ago and since unloaded.
+        if !data.ensure_loaded(map1) || !data.ensure_loaded(map2) {
+            return;
+        }
         let (name1, bridge1) = replace(&mut data.gen.generation[map1].bridges[bridge_id1], (String::with_capacity(0), Vec::with_capacity(0)));
         let (name2, bridge2) = replace(&mut data.gen.generation[map2].bridges[bridge_id2], (String::with_capacity(0), Vec::with_capacity(0)));
         data.world.glue_bridge(map1, &bridge1, map2, &bridge2, flip);
@@ -135,7 +198,7 @@ impl Generator {
         data.gen.generation[map2].bridges[bridge_id2] = (name2, bridge2);
     }
 
-    pub fn expand(map_id: usize, bridge_id: usize, data: &mut GameData) {
+    fn expand_bridge(map_id: usize, bridge_id: usize, data: &mut GameData) {
         if data.gen.used(map_id).bridges.len() == 0 {
             return;
         }
@@ -148,6 +211,75 @@ impl Generator {
         }
     }
 
+    pub fn expand(map_id: usize, bridge_id: usize, data: &mut GameData) {
+        Self::expand_bridge(map_id, bridge_id, data);
+        Self::connect_new(data);
+    }
+
+    fn connect_depth(map_id: usize, data: &GameData) -> usize {
+        match data.gen.used(map_id).connect_depth {
+            Some(depth) => depth,
+            None => data.resources.as_ref().options.bridge_depth as usize,
+        }
+    }
+
+    /// Connects every bridge which can be connected on a map,
+    /// then the bridges of the maps those lead to, up to depth maps away.
+    /// Maps that are not loaded are not followed.
+    pub fn connect_around(map_id: usize, depth: usize, data: &mut GameData) {
+        let mut visited = HashSet::new();
+        let mut next = VecDeque::from([(map_id, depth)]);
+        while let Some((id, depth)) = next.pop_front() {
+            if depth == 0 || id >= data.gen.generation.len() || !visited.insert(id) {
+                continue;
+            }
+            for bridge_id in 0..data.gen.generation[id].bridges.len() {
+                let gate = match data.gen.generation[id].bridges[bridge_id].1.first() {
+                    Some(gate) => *gate,
+                    None => continue,
+                };
+                let through = match data.world.get(id) {
+                    Some(mapdata) => mapdata.map.through(gate.tile, gate.gate),
+                    None => break,
+                };
+                if let ThroughResult::Generate(_) = through {
+                    Self::expand_bridge(id, bridge_id, data);
+                }
+                let through = match data.world.get(id) {
+                    Some(mapdata) => mapdata.map.through(gate.tile, gate.gate),
+                    None => break,
+                };
+                if let ThroughResult::Exists((to, _, _)) = through {
+                    if data.world.is_loaded(to.map) {
+                        next.push_back((to.map, depth-1));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Connects bridges around every map made since this was last called.
+    /// Maps made while doing this are left pending until the player arrives
+    /// on them, so that generation does not run away.
+    fn connect_new(data: &mut GameData) {
+        let made = take(&mut data.gen.unconnected);
+        for map_id in made {
+            let depth = Self::connect_depth(map_id, data);
+            Self::connect_around(map_id, depth, data);
+        }
+        let made_while_connecting = take(&mut data.gen.unconnected);
+        data.gen.pending.extend(made_while_connecting);
+    }
+
+    /// Called when the player enters a map, so that the connected maps
+    /// keep reaching as far ahead of the player as they walk.
+    pub fn arrive(map_id: usize, data: &mut GameData) {
+        if data.gen.pending.remove(&map_id) {
+            data.gen.unconnected.push(map_id);
+            Self::connect_new(data);
+        }
+    }
+
     /// Needs to be a list from the top overmap to the starting map
     pub fn start_branch(gens: &[&str], data: &mut GameData) -> Option<usize> {
         if gens.len() == 0 {
@@ -177,33 +309,37 @@ impl Generator {
                 break;
             }
         }
+        Self::connect_new(data);
         return Some(prev_parent);
     }
 
-    // TODO: ALL BRIDGES THAT CAN BE CONNECTED SHOULD LOAD WHEN A MAP LOADS
-    // TODO: ALL BRIDGES THAT CAN BE CONNECTED SHOULD LOAD WHEN A MAP LOADS
-    // TODO: ALL BRIDGES THAT CAN BE CONNECTED SHOULD LOAD WHEN A MAP LOADS
     fn create_map(parent: Option<usize>, gen_name: &str, data: &mut GameData) -> Option<usize> {
         let GenerationData {
             mapgen,
             templates,
             mut contains,
-            mut connect
+            mut connect,
+            connect_depth,
+            set_flags,
+            music,
         } = from_json(&data.resources.as_ref().path.maps.join(gen_name.to_string()+".json"), data.resources.as_mut())?;
         
-        if let Some(mut templates) = templates {
-            for template in templates.drain(..) {
-                let name = template.name.to_string();
-                let id = data.entities.add_template(template);
-                data.gen.template_names.insert(name, id);
-            }
+        if let Some(templates) = templates {
+            let repeated = !data.gen.template_files.insert(gen_name.to_string());
+            Self::add_templates(templates, repeated, data);
         }
         
+        for (flag, value) in set_flags.iter() {
+            data.flags.set(flag, *value);
+        }
+
         let mut mapdata = data.world.next_map();
         let mut for_generation = UsedByGeneration::default();
         mapdata.last_access = data.updates.current_time;
-        let map_id = data.world.next();
+        mapdata.music = music;
+        for_generation.name = gen_name.to_string();
         for_generation.parent = parent;
+        for_generation.connect_depth = connect_depth;
         for (s1, s2, flip) in connect.drain(..) {
             for_generation.bridge_connect.insert(s1.clone(), (s2.clone(), flip));
             for_generation.bridge_connect.insert(s2, (s1, flip));
@@ -218,6 +354,7 @@ impl Generator {
         }
         use BridgeLocation::Here;
         if let Some(mapgen) = mapgen {
+            let map_id = data.world.reserve();
             let entv = match mapgen {
                 MapGen::Euclid(ref inside_mapgen) => {
                     let (map, bridges) = EuclidMap::build(map_id, &inside_mapgen, data);
@@ -226,6 +363,9 @@ impl Generator {
                         for_generation.bridge_to.insert(name.clone(), Here(id));
                     }
                     for_generation.bridges = bridges;
+                    if let Some(region_maps) = &inside_mapgen.region_maps {
+                        EuclidMap::add_regions(&mut mapdata, region_maps, &inside_mapgen.region_key);
+                    }
                     EuclidMap::add_objects(map_id, &mut mapdata, &inside_mapgen.object_maps, &inside_mapgen.object_key, data)
                 },
                 MapGen::Sparse(inside_mapgen) => {
@@ -241,10 +381,11 @@ impl Generator {
     
             data.gen.generation.push(for_generation);
             // Also pushes bridges and the name into here
-            let ret =  data.world.finalize(mapdata);
+            let ret =  data.world.finalize(map_id, mapdata);
             for (create_ent, trav, info) in entv.iter() {
                 create_ent.call(data, *trav, info);
             }
+            data.gen.unconnected.push(ret);
             return Some(ret);
         } else {
             // TODO: fix this
@@ -255,6 +396,8 @@ impl Generator {
     }
     
     pub fn make(gen_name: &str, data: &mut GameData) -> Option<usize> {
-        Self::create_map(None, gen_name, data)
+        let map_id = Self::create_map(None, gen_name, data);
+        Self::connect_new(data);
+        map_id
     }
 }
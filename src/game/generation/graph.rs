@@ -0,0 +1,114 @@
+use super::{Generator, UsedByGeneration, NameOrID, inverse_len};
+use crate::game::{MapData, Map, MapHandler, ThroughResult, MapID, MapGate};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+fn node_name(gen: &Generator, id: usize) -> String {
+    if id < gen.generation.len() {
+        format!("m{}", id)
+    } else {
+        format!("g{}", inverse_len(id))
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('"', "\\\"")
+}
+
+fn label(used: &UsedByGeneration, id: usize) -> String {
+    if id == 0 && used.name.is_empty() {
+        return "void".to_string();
+    }
+    escape(&format!("{} ({})", used.name, id))
+}
+
+/// Finds the name of the bridge on a map that contains the given gate.
+fn bridge_with(used: &UsedByGeneration, gate: MapGate) -> Option<(usize, &str)> {
+    for (i, (name, bridge)) in used.bridges.iter().enumerate() {
+        if bridge.contains(&gate) {
+            return Some((i, name));
+        }
+    }
+    None
+}
+
+impl Generator {
+    /// Describes how the world is wired as a Graphviz DOT graph.
+    /// Maps are nodes, labelled with the generator that made them;
+    /// generations without a map and children which have not been generated yet
+    /// are drawn with dotted outlines, connected to their parent by dotted edges.
+    /// Bridges are solid edges labelled by name, length and whether they are flipped,
+    /// and bridges that have not been connected yet lead to a dashed point.
+    /// Maps which are not loaded are requested from `unloaded`;
+    /// if that returns None, only the generation data of the map is drawn.
+    pub fn world_dot<F>(&self, world: &MapHandler, mut unloaded: F) -> String
+    where F: FnMut(MapID) -> Option<Box<MapData>> {
+        let mut out = String::from("digraph world {\n    node [shape=box];\n");
+        let mut seen_edges = HashSet::new();
+        for (i, used) in self.without_map_iter() {
+            let _ = writeln!(out, "    {} [label=\"{}\", style=dotted];", node_name(self, i), label(used, i));
+        }
+        for id in 0..self.generation.len() {
+            let used = &self.generation[id];
+            let _ = writeln!(out, "    m{} [label=\"{}\"];", id, label(used, id));
+            let from_file;
+            let map = match world.get(id) {
+                Some(mapdata) => &mapdata.map,
+                None => {
+                    from_file = unloaded(id);
+                    match &from_file {
+                        Some(mapdata) => &mapdata.map,
+                        None => {
+                            let _ = writeln!(out, "    m{} [color=gray];", id);
+                            continue;
+                        }
+                    }
+                }
+            };
+            for (bridge_id, (name, bridge)) in used.bridges.iter().enumerate() {
+                if bridge.len() == 0 {
+                    continue;
+                }
+                match map.through(bridge[0].tile, bridge[0].gate) {
+                    ThroughResult::Exists((to, to_gate, flip)) if to.map < self.generation.len() => {
+                        let other = bridge_with(&self.generation[to.map], MapGate {tile: to.tile, gate: to_gate});
+                        let (other_id, other_name) = other.unwrap_or((usize::MAX, "?"));
+                        let key = if (id, bridge_id) <= (to.map, other_id) {
+                            ((id, bridge_id), (to.map, other_id))
+                        } else {
+                            ((to.map, other_id), (id, bridge_id))
+                        };
+                        if !seen_edges.insert(key) {
+                            continue;
+                        }
+                        let _ = writeln!(out, "    m{} -> m{} [dir=none, label=\"{} - {}\\nlength {}{}\"];",
+                            id, to.map, name, other_name, bridge.len(), if flip == 1 {", flipped"} else {""});
+                    },
+                    ThroughResult::Generate(_) => {
+                        let _ = writeln!(out, "    loose_{}_{} [shape=point];", id, bridge_id);
+                        let _ = writeln!(out, "    m{} -> loose_{}_{} [dir=none, style=dashed, label=\"{}\\nlength {}\"];",
+                            id, id, bridge_id, name, bridge.len());
+                    },
+                    _ => ()
+                }
+            }
+        }
+        for (id, used) in self.generation.iter().enumerate().chain(self.without_map_iter()) {
+            if let Some(parent) = used.parent {
+                let _ = writeln!(out, "    {} -> {} [style=dotted];", node_name(self, parent), node_name(self, id));
+            }
+            for (i, child) in used.children.iter().enumerate() {
+                if let NameOrID::Ungenerated(child_name) = child {
+                    let _ = writeln!(out, "    u{}_{} [label=\"{}\", style=dotted, color=gray];", node_name(self, id), i, escape(child_name));
+                    let _ = writeln!(out, "    {} -> u{}_{} [style=dotted, color=gray];", node_name(self, id), node_name(self, id), i);
+                }
+            }
+        }
+        out.push_str("}\n");
+        return out;
+    }
+
+    fn without_map_iter(&self) -> impl Iterator<Item = (usize, &UsedByGeneration)> {
+        self.generation_without_map.iter().enumerate().map(|(i, used)| (inverse_len(i), used))
+    }
+}
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use crate::game::entity_handler::*;
-use crate::common::Array2D;
-use super::{Bridge, Generator};
+use crate::game::Condition;
+use crate::common::{Array2D, Ambient};
+use super::{Bridge, Generator, Region};
 use std::collections::{HashSet, HashMap};
 pub use super::color_generation::*;
 
@@ -22,19 +23,32 @@ pub struct NameLength {
 pub struct ObjectGen {
     pub entity: Option<EntityEnum>,
     pub entity_info: Option<Value>,
-    pub object: Option<String>
+    pub object: Option<String>,
+    // Names this tile, so that it can be teleported to
+    #[serde(default)]
+    pub location: Option<String>,
+    // Only placed if this holds when the map is made
+    #[serde(default)]
+    pub condition: Option<Condition>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Floor {
     pub ch: char,
-    pub tile: DynTile
+    pub tile: DynTile,
+    // Played when something walks onto this floor
+    #[serde(default)]
+    pub footstep: Option<String>,
 }
 
 fn from_strings<'de, D>(deserializer: D) -> Result<Array2D<char>, D::Error>
 where D: serde::Deserializer<'de> {
     return Ok(Array2D::from_strs(Vec::<String>::deserialize(deserializer)?));
 }
+fn from_optional_strings<'de, D>(deserializer: D) -> Result<Option<Array2D<char>>, D::Error>
+where D: serde::Deserializer<'de> {
+    return Ok(Some(Array2D::from_strs(Vec::<String>::deserialize(deserializer)?)));
+}
 fn from_stringses<'de, D>(deserializer: D) -> Result<Vec<Array2D<char>>, D::Error>
 where D: serde::Deserializer<'de> {
     return Ok(Vec::<Vec<String>>::deserialize(deserializer)?.drain(..).map(|v| Array2D::from_strs(v)).collect());
@@ -48,7 +62,18 @@ pub struct GenerationData {
     pub contains: HashMap<String, Vec<String>>,
     // internal only
     pub connect: Vec<(String, String, bool)>,
-    pub templates: Option<Vec<Template>>,
+    // May use the global templates as bases, but not replace them
+    pub templates: Option<Vec<TemplateDef>>,
+    // How many maps away bridges are connected when this map is made;
+    // uses the option in options.json if not given
+    #[serde(default)]
+    pub connect_depth: Option<usize>,
+    // Flags set to these values when the map is made
+    #[serde(default)]
+    pub set_flags: HashMap<String, i64>,
+    // Crossfaded to when the player enters a map made by this
+    #[serde(default)]
+    pub music: Option<Ambient>,
 }
 
 #[derive(Deserialize)]
@@ -68,6 +93,11 @@ pub struct EuclidMapGen {
     #[serde(deserialize_with = "from_strings")]
     pub object_maps: Array2D<char>,
     pub object_key: HashMap<char, ObjectGen>,
+    // Laid out like object_maps; each char in region_key marks a tile of that region
+    #[serde(default, deserialize_with = "from_optional_strings")]
+    pub region_maps: Option<Array2D<char>>,
+    #[serde(default)]
+    pub region_key: HashMap<char, Region>,
     pub default_wall: DynTile,
     pub floors: Vec<Floor>,
 }
@@ -85,7 +115,12 @@ pub enum BridgeLocation {Child(usize), Here(usize)}
 pub enum NameOrID {Ungenerated(String), Generated(usize)}
 #[derive(Serialize,Deserialize,Default,Debug)]
 pub struct UsedByGeneration {
+    /// The name of the generation file this was made from.
+    #[serde(default)]
+    pub name: String,
     pub parent: Option<usize>,
+    #[serde(default)]
+    pub connect_depth: Option<usize>,
     pub bridges: Vec<(String, Bridge)>,
     pub bridge_to: HashMap<String, BridgeLocation>,
     pub bridge_connect: HashMap<String, (String, bool)>,
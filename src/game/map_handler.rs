@@ -9,12 +9,15 @@ use traverser::TraverserCore;
 use super::generation::UsedByGeneration;
 use super::identifiers::*;
 use super::{Time, GameData};
+use crate::common::Ambient;
+use super::entity_handler::OnInteract;
+use super::TemplateID;
 
 use rand::Rng;
-use crate::common::{TileStyle, TakeBox, UITile};
-use std::collections::HashMap;
-use std::ops::{Index, IndexMut};
-use serde::{Serialize,Deserialize};
+use crate::common::{TileStyle, UITile};
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize,Deserialize,Deserializer};
+use serde::de::IgnoredAny;
 
 pub fn void_tile() -> UniqTile {
     let mut rng = rand::thread_rng();
@@ -23,119 +26,341 @@ pub fn void_tile() -> UniqTile {
 
 pub type Bridge = Vec<MapGate>;
 
-trait RemoveVecCompatible {
-    fn has(&self, i: usize) -> bool;
-    fn next(&self) -> usize;
-}
-impl<T> RemoveVecCompatible for Vec<T> {
-    // TODO: This is completely broken.
-    // Has needs to be dependent on whether the map is currently loaded or not,
-    // including things that do object movement and stuff.
-    fn has(&self, i: usize) -> bool {
-        return i < self.len();
-    }
-    fn next(&self) -> usize {
-        return self.len();
-    }
-}
-
 // TODO: Create distinct layers for objects.
 #[derive(Serialize,Deserialize)]
 pub struct MapData {
     pub map: MapEnum,
     pub objects: HashMap<TileID, Vec<Object>>,
     pub last_access: Time,
+    // Set while the map is saved to its file, for catching up its entities when it loads again.
+    #[serde(default)]
+    pub unloaded_at: Option<Time>,
+    #[serde(default)]
+    pub regions: Vec<Region>,
+    // Indices into regions for each tile that is part of one
+    #[serde(default)]
+    pub region_tiles: HashMap<TileID, Vec<usize>>,
+    #[serde(default)]
+    pub closing_doors: Vec<DoorTimer>,
+    // Played when the player enters the map
+    #[serde(default)]
+    pub music: Option<Ambient>,
+}
+
+/// An open door which will close by itself.
+#[derive(Serialize,Deserialize)]
+pub struct DoorTimer {
+    pub at: Time,
+    pub tile: TileID,
+    pub open: TemplateID,
+    pub closed: TemplateID,
+}
+
+/// A named area of a map with actions for the player entering and leaving it.
+/// The actions are given Region::target rather than any object in the world.
+/// A cutscene is a Talk with a dialogue, so it can be started from here too.
+#[derive(Serialize,Deserialize,Clone)]
+pub struct Region {
+    pub name: String,
+    #[serde(default)]
+    pub on_enter: Option<OnInteract>,
+    #[serde(default)]
+    pub on_exit: Option<OnInteract>,
+    /// Also runs the actions for entities other than the player.
+    #[serde(default)]
+    pub any_entity: bool,
+}
+
+impl Region {
+    /// What region actions act on. The object is never on any tile,
+    /// so actions which change or remove their object do nothing.
+    pub fn target(tile: UniqTile) -> ObjTile {
+        (Object {entity_id: None, template_id: TemplateID::MAX}, tile)
+    }
+}
+
+/// Where a map with a particular id currently is.
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+pub enum MapState {
+    Loaded,
+    /// Saved to its .map file, and may be loaded back in.
+    Unloaded,
+    /// Could not be loaded from its file; tiles leading into it lead to the void.
+    Invalid,
+    /// The id has been given out, but the map is still being built.
+    Generating,
+    /// No map has ever had this id.
+    Nonexistent,
+}
+
+#[derive(Serialize)]
+enum MapSlot {
+    Loaded(Box<MapData>),
+    Unloaded,
+    Invalid,
+    Generating,
+}
+
+#[derive(Deserialize)]
+enum MapSlot2 {
+    Loaded(Box<MapData>),
+    Unloaded,
+    Invalid,
+    Generating,
+}
+/// Saves from before load states were tracked kept each map in a TakeBox,
+/// which was empty while the map was unloaded.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedSlot {
+    Slot(MapSlot2),
+    TakeBox {this: Option<Box<MapData>>},
+    // Held an InvalidMap, which no longer exists
+    InvalidTakeBox {#[allow(dead_code)] this: IgnoredAny},
+}
+impl<'de> Deserialize<'de> for MapSlot {
+    fn deserialize<D>(deserializer: D) -> Result<MapSlot, D::Error>
+    where D: Deserializer<'de> {
+        Ok(match SavedSlot::deserialize(deserializer)? {
+            SavedSlot::Slot(slot) => match slot {
+                MapSlot2::Loaded(map) => MapSlot::Loaded(map),
+                MapSlot2::Unloaded => MapSlot::Unloaded,
+                MapSlot2::Invalid => MapSlot::Invalid,
+                // Nothing can be generating while the game is saved
+                MapSlot2::Generating => MapSlot::Invalid,
+            },
+            SavedSlot::TakeBox {this: Some(map)} => MapSlot::Loaded(map),
+            SavedSlot::TakeBox {this: None} => MapSlot::Unloaded,
+            SavedSlot::InvalidTakeBox {..} => MapSlot::Invalid,
+        })
+    }
+}
+
+/// Counts of map loading and unloading over this session, for the debug output.
+#[derive(Default)]
+pub struct MapStats {
+    pub loads: usize,
+    pub unloads: usize,
+    pub bytes_loaded: u64,
+    pub bytes_saved: u64,
+}
+
+impl MapStats {
+    pub fn summary(&self) -> String {
+        format!("{} loads ({} bytes), {} unloads ({} bytes)", self.loads, self.bytes_loaded, self.unloads, self.bytes_saved)
+    }
 }
 
 #[derive(Serialize,Deserialize)]
 pub struct MapHandler {
-    maps: Vec<TakeBox<MapData>>
+    maps: Vec<MapSlot>,
+    // Maps seen in the last line of sight scan; these are never unloaded.
+    #[serde(skip)]
+    visible: HashSet<MapID>,
+    #[serde(skip)]
+    pub stats: MapStats,
 }
 
 impl MapHandler {
     pub fn new() -> Self {
         let mut this = Self {
             maps: Vec::new(),
+            visible: HashSet::new(),
+            stats: MapStats::default(),
         };
 
-        this.maps.push(TakeBox::new(MapData {
+        this.maps.push(MapSlot::Loaded(Box::new(MapData {
             map: VoidMap::new(0).into(),
             objects: HashMap::new(),
-            last_access: 0
-        }));
+            last_access: 0,
+            unloaded_at: None,
+            regions: Vec::new(),
+            region_tiles: HashMap::new(),
+            closing_doors: Vec::new(),
+            music: None,
+        })));
         return this;
     }
 
+    pub fn state(&self, id: MapID) -> MapState {
+        match self.maps.get(id) {
+            Some(MapSlot::Loaded(_)) => MapState::Loaded,
+            Some(MapSlot::Unloaded) => MapState::Unloaded,
+            Some(MapSlot::Invalid) => MapState::Invalid,
+            Some(MapSlot::Generating) => MapState::Generating,
+            None => MapState::Nonexistent,
+        }
+    }
+
+    pub fn is_loaded(&self, id: MapID) -> bool {
+        return self.state(id) == MapState::Loaded;
+    }
+
+    /// Returns the map if it is currently loaded.
+    pub fn get(&self, id: MapID) -> Option<&MapData> {
+        match self.maps.get(id) {
+            Some(MapSlot::Loaded(map)) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: MapID) -> Option<&mut MapData> {
+        match self.maps.get_mut(id) {
+            Some(MapSlot::Loaded(map)) => Some(map),
+            _ => None,
+        }
+    }
+
     /// Iter consumes the surrounding container of the vec,
     /// so this just returns the iterator index.
     /// Only returns maps which are currently loaded.
     pub fn nonref_iter(&self, x: usize) -> Option<(usize, &MapData)> {
         for i in x..self.maps.len() {
-            if self.maps[i].is_some() {
-                return Some((i, self.maps[i].as_ref()));
+            if let Some(map) = self.get(i) {
+                return Some((i, map));
             }
         }
         return None;
     }
 
-    pub fn iter(&self) -> std::slice::Iter<'_, TakeBox<MapData>> {
-        return self.maps.iter();
+    /// Iterates over the maps which are currently loaded.
+    pub fn iter(&self) -> impl Iterator<Item = (MapID, &MapData)> {
+        return self.maps.iter().enumerate().filter_map(|(i, slot)| match slot {
+            MapSlot::Loaded(map) => Some((i, map.as_ref())),
+            _ => None,
+        });
     }
-    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, TakeBox<MapData>> {
-        return self.maps.iter_mut();
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (MapID, &mut MapData)> {
+        return self.maps.iter_mut().enumerate().filter_map(|(i, slot)| match slot {
+            MapSlot::Loaded(map) => Some((i, map.as_mut())),
+            _ => None,
+        });
+    }
+
+    /// Closes doors on loaded maps whose time has come.
+    /// A door with an entity standing in it stays open until the entity leaves.
+    pub fn close_doors(&mut self, now: Time) {
+        for (_, mapdata) in self.iter_mut() {
+            if mapdata.closing_doors.is_empty() {
+                continue;
+            }
+            let objects = &mut mapdata.objects;
+            mapdata.closing_doors.retain(|timer| {
+                if timer.at > now {
+                    return true;
+                }
+                let on_tile = match objects.get_mut(&timer.tile) {
+                    Some(on_tile) => on_tile,
+                    None => return false,
+                };
+                if on_tile.iter().any(|obj| obj.entity_id.is_some()) {
+                    return true;
+                }
+                for obj in on_tile.iter_mut() {
+                    if obj.template_id == timer.open {
+                        obj.template_id = timer.closed;
+                        break;
+                    }
+                }
+                false
+            });
+        }
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        return self.iter().count();
+    }
+
+    /// Marks a map as used now, for deciding which maps to unload.
+    pub fn touch(&mut self, id: MapID, time: Time) {
+        if let Some(mapdata) = self.get_mut(id) {
+            mapdata.last_access = time;
+        }
+    }
+
+    /// Replaces the set of maps which can currently be seen.
+    pub fn set_visible(&mut self, visible: HashSet<MapID>, time: Time) {
+        for id in visible.iter() {
+            self.touch(*id, time);
+        }
+        self.visible = visible;
+    }
+
+    pub fn is_visible(&self, id: MapID) -> bool {
+        return self.visible.contains(&id);
     }
 
     pub fn next_map(&self) -> MapData {
         MapData {
             map: UninitializedMap.into(),
             objects: HashMap::new(),
-            last_access: 0
+            last_access: 0,
+            unloaded_at: None,
+            regions: Vec::new(),
+            region_tiles: HashMap::new(),
+            closing_doors: Vec::new(),
+            music: None,
         }
     }
     /// Used for maps which were, for some reason, not loaded
     /// correctly from a file.
     pub fn invalidate(&mut self, id: MapID) {
-        self.maps[id].replace(Box::new(MapData {
-            map: InvalidMap.into(),
-            objects: HashMap::new(),
-            last_access: 0
-        }));
+        if id < self.maps.len() {
+            self.maps[id] = MapSlot::Invalid;
+        }
     }
-    pub fn finalize(&mut self, mapdata: MapData) -> usize {
-        self.maps.push(TakeBox::new(mapdata));
+    /// Gives out the id of a map that is about to be built.
+    /// The map is Generating until it is finalized.
+    pub fn reserve(&mut self) -> MapID {
+        self.maps.push(MapSlot::Generating);
         return self.maps.len()-1;
     }
-    
-    pub fn next(&self) -> MapID {
-        return self.maps.next();
+    pub fn finalize(&mut self, id: MapID, mapdata: MapData) -> MapID {
+        if id < self.maps.len() {
+            self.maps[id] = MapSlot::Loaded(Box::new(mapdata));
+        }
+        return id;
     }
     
-    pub fn take(&mut self, id: usize) -> Box<MapData> {
-        return self.maps[id].take();
+    /// Removes a loaded map so it can be saved, marking it as Unloaded.
+    pub fn take(&mut self, id: MapID) -> Option<Box<MapData>> {
+        if !self.is_loaded(id) {
+            return None;
+        }
+        match std::mem::replace(&mut self.maps[id], MapSlot::Unloaded) {
+            MapSlot::Loaded(map) => Some(map),
+            _ => unreachable!(),
+        }
     }
-    pub fn replace(&mut self, id: usize, map: Box<MapData>) {
-        return self.maps[id].replace(map);
+    /// Puts a map back after it has been loaded from its file.
+    /// Does nothing to maps which have never been generated.
+    pub fn replace(&mut self, id: MapID, map: Box<MapData>) {
+        if id < self.maps.len() {
+            self.maps[id] = MapSlot::Loaded(map);
+        }
     }
     
     pub fn passable(&self, tile: UniqTile) -> bool {
-        return self.maps[tile.map as usize].as_ref().map.passable(tile.tile);
+        match self.get(tile.map) {
+            Some(mapdata) => mapdata.map.passable(tile.tile),
+            None => false,
+        }
     }
     
     // Any code not in TraverserCore that calls this function is a BUG!
     // ALL map movement, even IN MAP FILES, MUST be interpreted by traverser!
     pub fn through(&self, tile: UniqTile, gate: u8) -> ThroughResult {
-        if !self.tile_exists(tile) {
-            return ThroughResult::Exists((void_tile(),0,0));
-        }
-        let result = self.maps[tile.map].as_ref().map.through(tile.tile, gate);
+        let result = match self.get(tile.map) {
+            Some(mapdata) if mapdata.map.has_tile(tile.tile) => mapdata.map.through(tile.tile, gate),
+            _ => return ThroughResult::Exists((void_tile(),0,0)),
+        };
         match result {
             ThroughResult::Exists((t2,_a,_b)) => {
-                if self.maps[t2.map].is_none() {
-                    return ThroughResult::Load(t2.map);
-                } else if let MapEnum::InvalidMap(_) = self.maps[t2.map].as_ref().map {
-                    return ThroughResult::Exists((void_tile(),0,0));
-                } else {
-                    return result;
+                match self.state(t2.map) {
+                    MapState::Loaded => return result,
+                    MapState::Unloaded => return ThroughResult::Load(t2.map),
+                    MapState::Generating => return ThroughResult::None,
+                    MapState::Invalid | MapState::Nonexistent => return ThroughResult::Exists((void_tile(),0,0)),
                 }
             },
             _ => return result,
@@ -143,48 +368,72 @@ impl MapHandler {
     }
     
     pub fn has_map(&self, id: MapID) -> bool {
-        return self.maps.has(id);
+        return self.is_loaded(id);
     }
     
     pub fn tile_exists(&self, id: UniqTile) -> bool {
-        return self.has_map(id.map) && self.maps[id.map].as_ref().map.has_tile(id.tile)
+        match self.get(id.map) {
+            Some(mapdata) => mapdata.map.has_tile(id.tile),
+            None => false,
+        }
     }
     
     pub fn background(&self, tile: UniqTile) -> TileStyle {
-        let style = self.maps[tile.map].as_ref().map.background_style(tile.tile);
-        if !self.tile_exists(tile) {
-            return TileStyle {fg: None, bg: None};
+        match self.get(tile.map) {
+            Some(mapdata) if mapdata.map.has_tile(tile.tile) => mapdata.map.background_style(tile.tile),
+            _ => TileStyle {fg: None, bg: None},
         }
-        return style;
     }
     
-    pub fn glue_bridge(&mut self, map1: MapID, bridge1: &Vec<MapGate>, map2: MapID, bridge2: &Vec<MapGate>, flip: bool) {
-        if !flip {
-            self.maps[map1].as_mut().map.glue_one_side(bridge1, map2, bridge2);
-            self.maps[map2].as_mut().map.glue_one_side(bridge2, map1, bridge1);
-        } else {
-            self.maps[map1].as_mut().map.glue_one_side_flip(bridge1, map2, bridge2, 0);
-            self.maps[map2].as_mut().map.glue_one_side_flip(bridge2, map1, bridge1, 1);
+    /// Both maps must be loaded; returns whether the bridges were glued.
+    pub fn glue_bridge(&mut self, map1: MapID, bridge1: &Vec<MapGate>, map2: MapID, bridge2: &Vec<MapGate>, flip: bool) -> bool {
+        if !self.is_loaded(map1) || !self.is_loaded(map2) {
+            return false;
+        }
+        if let Some(mapdata) = self.get_mut(map1) {
+            if !flip {
+                mapdata.map.glue_one_side(bridge1, map2, bridge2);
+            } else {
+                mapdata.map.glue_one_side_flip(bridge1, map2, bridge2, 0);
+            }
         }
+        if let Some(mapdata) = self.get_mut(map2) {
+            if !flip {
+                mapdata.map.glue_one_side(bridge2, map1, bridge1);
+            } else {
+                mapdata.map.glue_one_side_flip(bridge2, map1, bridge1, 1);
+            }
+        }
+        return true;
     }
     
     pub fn connect(&mut self, first: UniqTile, gate1: u8, second: UniqTile, gate2: u8, flip: u8) {
         if !self.tile_exists(first) || !self.tile_exists(second) { return; }
         
-        self.maps[first.map].as_mut().map.one_sided_connect(first.tile, gate1, second, gate2, flip);
-        self.maps[second.map].as_mut().map.one_sided_connect(second.tile, gate2, first, gate1, flip);
+        if let Some(mapdata) = self.get_mut(first.map) {
+            mapdata.map.one_sided_connect(first.tile, gate1, second, gate2, flip);
+        }
+        if let Some(mapdata) = self.get_mut(second.map) {
+            mapdata.map.one_sided_connect(second.tile, gate2, first, gate1, flip);
+        }
     }
     
     // Attempts to move an object from tile1 to tile2
     // If tile1 does not exist, or the specified object does not exist on it, returns a random void tile
-    // If tile2 does not exist, returns tile1 and does not move the object
+    // If tile2 does not exist (or its map is not loaded), returns tile1 and does not move the object
     // Otherwise, changes the tile of the object and returns tile2.
     pub fn move_object(&mut self, tile1: UniqTile, tile2: UniqTile, obj_id: Object) -> UniqTile {
         let mut obj = None;
-        if !self.maps.has(tile1.map) {
+        if !self.tile_exists(tile1) {
             return void_tile();
         }
-        let map_objects = &mut self.maps[tile1.map].as_mut().objects;
+        if !self.tile_exists(tile2) {
+            return tile1;
+        }
+        let map_objects = match self.get_mut(tile1.map) {
+            Some(mapdata) => &mut mapdata.objects,
+            None => return void_tile(),
+        };
         let mut objects_remain = true;
         match map_objects.get_mut(&tile1.tile) {
             None => return void_tile(),
@@ -212,11 +461,32 @@ impl MapHandler {
         }
     }
     
-    pub fn create_obj(&mut self, tile: UniqTile, obj: Object) -> UniqTile {
-        if !self.maps.has(tile.map) || !self.maps[tile.map].as_ref().map.has_tile(tile.tile) {
-            return void_tile();
+    /// Returns whether the object was there to remove.
+    pub fn remove_obj(&mut self, tile: UniqTile, obj: Object) -> bool {
+        let objects = match self.objects_mut(tile) {
+            Some(objects) => objects,
+            None => return false,
+        };
+        let found = match objects.iter().position(|o| *o == obj) {
+            Some(index) => {
+                objects.swap_remove(index);
+                true
+            },
+            None => false,
+        };
+        if objects.len() == 0 {
+            if let Some(mapdata) = self.get_mut(tile.map) {
+                mapdata.objects.remove(&tile.tile);
+            }
         }
-        let map_objects = &mut self.maps[tile.map].as_mut().objects;
+        return found;
+    }
+
+    pub fn create_obj(&mut self, tile: UniqTile, obj: Object) -> UniqTile {
+        let map_objects = match self.get_mut(tile.map) {
+            Some(mapdata) if mapdata.map.has_tile(tile.tile) => &mut mapdata.objects,
+            _ => return void_tile(),
+        };
         
         match map_objects.get_mut(&tile.tile) {
             Some(objects) => {
@@ -231,7 +501,7 @@ impl MapHandler {
     }
 
     pub fn find_object(&mut self, tile: UniqTile, obj: Object) -> Option<&mut Object> {
-        for maybe_obj in self.maps[tile.map].as_mut().objects.get_mut(&tile.tile)? {
+        for maybe_obj in self.get_mut(tile.map)?.objects.get_mut(&tile.tile)? {
             if *maybe_obj == obj {
                 return Some(maybe_obj);
             }
@@ -241,32 +511,98 @@ impl MapHandler {
     
     pub fn objects_on(&self, tile: UniqTile) -> &[Object] {
         const EMPTY_VEC: [Object; 0] = [];
-        if !self.maps.has(tile.map) {
-            return &EMPTY_VEC;
-        }
-        if let Some(v) = self.maps[tile.map].as_ref().objects.get(&tile.tile) {
+        if let Some(v) = self.get(tile.map).and_then(|mapdata| mapdata.objects.get(&tile.tile)) {
             return &v;
         }
         return &EMPTY_VEC;
     }
-    pub fn objects_mut(&mut self, tile: UniqTile) -> Option<&mut Vec<Object>> {
-        if !self.maps.has(tile.map) {
-            return None;
+    /// The regions a tile is part of, each with the map it belongs to and its index there.
+    pub fn regions_on(&self, tile: UniqTile) -> Vec<((MapID, usize), &Region)> {
+        let mapdata = match self.get(tile.map) {
+            Some(mapdata) => mapdata,
+            None => return Vec::new(),
+        };
+        match mapdata.region_tiles.get(&tile.tile) {
+            Some(indices) => indices.iter().map(|i| ((tile.map, *i), &mapdata.regions[*i])).collect(),
+            None => Vec::new(),
         }
-        return self.maps[tile.map].as_mut().objects.get_mut(&tile.tile);
+    }
+    pub fn objects_mut(&mut self, tile: UniqTile) -> Option<&mut Vec<Object>> {
+        return self.get_mut(tile.map)?.objects.get_mut(&tile.tile);
     }
 }
 
-impl Index<MapID> for MapHandler {
-    type Output = MapData;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPEN: TemplateID = 1;
+    const CLOSED: TemplateID = 2;
+    const TILE: TileID = 7;
 
-    fn index(&self, index: usize) -> &Self::Output {
-        return self.maps[index].as_ref();
+    /// A world with an open door on the void map, which closes at the given time
+    fn world_with_door(at: Time) -> MapHandler {
+        let mut world = MapHandler::new();
+        let mapdata = world.get_mut(0).unwrap();
+        mapdata.objects.insert(TILE, vec![Object {entity_id: None, template_id: OPEN}]);
+        mapdata.closing_doors.push(DoorTimer {at, tile: TILE, open: OPEN, closed: CLOSED});
+        world
+    }
+
+    fn door(world: &MapHandler) -> TemplateID {
+        world.objects_on(UniqTile {map: 0, tile: TILE})[0].template_id
+    }
+
+    fn timers(world: &MapHandler) -> usize {
+        world.get(0).unwrap().closing_doors.len()
+    }
+
+    #[test]
+    fn door_closes_when_its_time_comes() {
+        let mut world = world_with_door(10);
+        world.close_doors(9);
+        assert_eq!(door(&world), OPEN);
+        assert_eq!(timers(&world), 1);
+        world.close_doors(10);
+        assert_eq!(door(&world), CLOSED);
+        assert_eq!(timers(&world), 0);
+    }
+
+    #[test]
+    fn door_stays_open_while_occupied() {
+        let mut world = world_with_door(10);
+        let entity = Object {entity_id: Some(3), template_id: 5};
+        world.get_mut(0).unwrap().objects.get_mut(&TILE).unwrap().push(entity);
+        world.close_doors(20);
+        assert_eq!(door(&world), OPEN);
+        assert_eq!(timers(&world), 1);
+        world.get_mut(0).unwrap().objects.get_mut(&TILE).unwrap().retain(|obj| *obj != entity);
+        world.close_doors(21);
+        assert_eq!(door(&world), CLOSED);
+    }
+
+    #[test]
+    fn reopened_door_closes_again() {
+        let mut world = world_with_door(10);
+        world.close_doors(10);
+        let mapdata = world.get_mut(0).unwrap();
+        mapdata.objects.get_mut(&TILE).unwrap()[0].template_id = OPEN;
+        mapdata.closing_doors.push(DoorTimer {at: 30, tile: TILE, open: OPEN, closed: CLOSED});
+        world.close_doors(20);
+        assert_eq!(door(&world), OPEN);
+        world.close_doors(30);
+        assert_eq!(door(&world), CLOSED);
     }
-}
 
-impl IndexMut<MapID> for MapHandler {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        return self.maps[index].as_mut();
+    #[test]
+    fn timer_of_a_door_closed_by_hand_does_nothing() {
+        let mut world = world_with_door(10);
+        let mapdata = world.get_mut(0).unwrap();
+        mapdata.objects.get_mut(&TILE).unwrap()[0].template_id = CLOSED;
+        mapdata.objects.get_mut(&TILE).unwrap().push(Object {entity_id: None, template_id: 9});
+        world.close_doors(10);
+        assert_eq!(door(&world), CLOSED);
+        assert_eq!(world.objects_on(UniqTile {map: 0, tile: TILE})[1].template_id, 9);
+        assert_eq!(timers(&world), 0);
     }
 }
@@ -4,7 +4,7 @@ mod sparsemap; pub use sparsemap::SparseMap;
 mod euclidmap; pub use euclidmap::EuclidMap;
 mod gate;
 //mod tile;
-mod invalidmap; pub use invalidmap::{UninitializedMap, InvalidMap};
+mod invalidmap; pub use invalidmap::UninitializedMap;
 
 
 use gate::Gate;
@@ -17,7 +17,7 @@ use super::TraverserCore;
 use serde::{Serialize,Deserialize};
 use enum_dispatch::enum_dispatch;
 use crate::common::TileStyle;
-use super::{TileID, void_tile, ThroughResult, ToTile, Bridge, MapData, MapID, UniqTile, MapGate, Traverser};
+use super::{TileID, void_tile, ThroughResult, ToTile, Bridge, MapData, Region, MapID, UniqTile, MapGate, Traverser};
 use crate::game::GameData;
 
 use std::collections::HashMap;
@@ -29,8 +29,6 @@ pub enum MapEnum {
     VoidMap, EuclidMap, SparseMap,
     // A map will be put into place soon
     UninitializedMap,
-    // For when a map cannot be loaded from a file; points to the void map
-    InvalidMap
 }
 
 
@@ -60,6 +58,11 @@ pub trait Map {
     fn background_style(&self, tile: TileID) -> TileStyle;
 
     fn random_tile(&self) -> TileID;
+
+    // The sound of walking onto this tile, if its floor has one
+    fn footstep(&self, _tile: TileID) -> Option<&str> {
+        None
+    }
     
     // Returns which gates from this tile are unconnected
     fn find_unconnected(&self, tile: TileID) -> [bool;4] {
@@ -3,7 +3,7 @@ use serde::{Serialize,Deserialize};
 use serde_json::Value;
 use std::collections::{HashSet, HashMap};
 use crate::game::{entity_handler::EntityEnum, generation::{DynTile, EuclidMapGen, ObjectGen}};
-use super::{MapGate, GateMap, MapEnum, MapData, ThroughResult, Gate, TileID, Traverser, TileStyle, Bridge, Map, GameData, MapID, UniqTile};
+use super::{MapGate, GateMap, MapEnum, MapData, Region, ThroughResult, Gate, TileID, Traverser, TileStyle, Bridge, Map, GameData, MapID, UniqTile};
 use crate::common::{Array2D, TakeBox, item_iterate, StraightBridge, SideToCoord, AdjacentFloorIter};
 use rand::Rng;
 use crate::errstr;
@@ -18,7 +18,10 @@ pub struct EuclidMap {
     space: Array2D<u8>,
     gatemap: GateMap,
     default_wall: DynTile,
-    floors: Vec<DynTile>
+    floors: Vec<DynTile>,
+    // The footstep sound of each floor
+    #[serde(default)]
+    footsteps: Vec<Option<String>>,
 }
 
 impl Map for EuclidMap {
@@ -72,6 +75,17 @@ impl Map for EuclidMap {
         return self.gatemap.contains_key(&MapGate {tile, gate}) || next_tile != None;
     }
     
+    fn footstep(&self, tile: TileID) -> Option<&str> {
+        if !self.has_tile(tile) {
+            return None;
+        }
+        let floor_type = self.space[tile as usize] as usize;
+        if floor_type == 0 {
+            return None;
+        }
+        self.footsteps.get(floor_type-1)?.as_deref()
+    }
+
     fn background_style(&self, tile: TileID) -> TileStyle {
         let floor_type = self.space[tile as usize];
         if floor_type == 0 {
@@ -84,7 +98,7 @@ impl Map for EuclidMap {
 
 impl EuclidMap {
     pub fn new(id: MapID) -> Self {
-        Self { id: id, space: Array2D::with_capacity(0), gatemap: HashMap::new(), default_wall: DynTile::default(), floors: Vec::new() }
+        Self { id: id, space: Array2D::with_capacity(0), gatemap: HashMap::new(), default_wall: DynTile::default(), floors: Vec::new(), footsteps: Vec::new() }
     }
 
     fn next(&self, tile: usize, gate: u8) -> Option<TileID> {
@@ -172,6 +186,7 @@ impl EuclidMap {
         for (i, floor) in mapgen.floors.iter().enumerate() {
             floor_map.insert(floor.ch, i as u8);
             this.floors.push(floor.tile.clone());
+            this.footsteps.push(floor.footstep.clone());
         }
         this.default_wall = mapgen.default_wall;
         let mut bridges: HashMap<char, StraightBridge> = HashMap::new();
@@ -192,11 +207,33 @@ impl EuclidMap {
         return (this.into(), final_bridges);
     }
 
+    pub fn add_regions(mapdata: &mut MapData, region_map: &Array2D<char>, region_key: &HashMap<char, Region>) {
+        let mut indices = HashMap::new();
+        for coord in region_map.coord_iter() {
+            let ch = region_map[coord];
+            if let Some(region) = region_key.get(&ch) {
+                let index = *indices.entry(ch).or_insert_with(|| {
+                    mapdata.regions.push(region.clone());
+                    mapdata.regions.len()-1
+                });
+                mapdata.region_tiles.entry(region_map.to_1d(coord) as TileID).or_insert_with(Vec::new).push(index);
+            }
+        }
+    }
+
     pub fn add_objects<'a>(map_id: usize, mapdata: &mut MapData, object_map: &Array2D<char>, object_key: &'a HashMap<char, ObjectGen>, data: &mut GameData) -> Vec<(EntityEnum, Traverser, &'a Option<Value>)> {
         let mut entv = Vec::new();
         for coord in object_map.coord_iter() {
             if let Some(gen) = object_key.get(&object_map[coord]) {
+                if let Some(condition) = &gen.condition {
+                    if !condition.check(&data.flags) {
+                        continue;
+                    }
+                }
                 let tile = UniqTile {map: map_id, tile: object_map.to_1d(coord) as TileID};
+                if let Some(location) = &gen.location {
+                    data.gen.locations.insert(location.clone(), tile);
+                }
                 if gen.entity.is_some() {
                     entv.push((*gen.entity.as_ref().unwrap(), Traverser::new(tile), &gen.entity_info));
                 }
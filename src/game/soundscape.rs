@@ -0,0 +1,184 @@
+use super::{GameData, Traverser, UniqTile};
+use crate::common::{Emitter, EmitterSource};
+use std::collections::{HashMap, VecDeque};
+
+// Nothing further than this many steps away is heard, whatever its radius
+pub const HEARING_RANGE: usize = 32;
+// The radius of one-shot sounds which do not give their own
+pub const DEFAULT_RADIUS: usize = 12;
+
+/// How a tile is heard from somewhere: the fewest steps it takes to walk there,
+/// and how far right (or, if negative, left) those steps went on the screen.
+#[derive(Clone, Copy)]
+struct Heard {
+    distance: usize,
+    right: i32,
+}
+
+/// What the player hears from where they stand.
+/// Every sound in a turn is placed with the same walk, until the player moves.
+pub struct Hearing {
+    from: UniqTile,
+    heard: HashMap<UniqTile, Heard>,
+}
+
+/// Walks out from a traverser through gates, so that a tile through a portal
+/// is as close as the steps it takes to get there. Only loaded maps are walked.
+fn hearing_distances(from: Traverser, range: usize, data: &GameData) -> HashMap<UniqTile, Heard> {
+    let mut heard = HashMap::from([(from.tile, Heard {distance: 0, right: 0})]);
+    let mut queue = VecDeque::from([(from, Heard {distance: 0, right: 0})]);
+    while let Some((trav, here)) = queue.pop_front() {
+        if here.distance >= range {
+            continue;
+        }
+        // up, down, left, right
+        for (dir, right) in [(0, 0), (1, 0), (2, -1), (3, 1)] {
+            let Some(next) = data.travel(trav, dir) else {continue};
+            if heard.contains_key(&next.tile) {
+                continue;
+            }
+            let there = Heard {distance: here.distance+1, right: here.right+right};
+            heard.insert(next.tile, there);
+            queue.push_back((next, there));
+        }
+    }
+    return heard;
+}
+
+/// The amplitude of a sound after falling off over its radius, or None if it is out of reach.
+fn attenuate(volume: u8, heard: Heard, radius: usize) -> Option<f64> {
+    if heard.distance > radius {
+        return None;
+    }
+    let falloff = 1. - heard.distance as f64/(radius+1) as f64;
+    Some(volume as f64/255.*falloff)
+}
+
+/// 0 is all the way left and 1 is all the way right.
+fn panning(heard: Heard) -> f64 {
+    if heard.distance == 0 {
+        return 0.5;
+    }
+    0.5 + 0.5*(heard.right as f64/heard.distance as f64).clamp(-1., 1.)
+}
+
+impl GameData {
+    pub fn player_traverser(&self) -> Option<Traverser> {
+        if !self.entities.player_data.created {
+            return None;
+        }
+        self.entities[self.entities.player_data.entity].loc.map(|(_, trav)| trav)
+    }
+
+    /// How far each tile is from the player, walked again only if the player has moved
+    /// since it was last walked, or if fresh is set.
+    fn hearing(&mut self, fresh: bool) -> Option<&HashMap<UniqTile, Heard>> {
+        let player = self.player_traverser()?;
+        let stale = match &self.hearing {
+            Some(hearing) => fresh || hearing.from != player.tile,
+            None => true,
+        };
+        if stale {
+            let heard = hearing_distances(player, HEARING_RANGE, self);
+            self.hearing = Some(Hearing {from: player.tile, heard});
+        }
+        self.hearing.as_ref().map(|hearing| &hearing.heard)
+    }
+
+    /// Plays a sound once from a tile, as loud as it is close to the player.
+    pub fn play_at(&mut self, sound: &str, tile: UniqTile, volume: u8, radius: usize) -> bool {
+        let Some(heard) = self.hearing(false).and_then(|heard| heard.get(&tile).copied()) else {return false};
+        let Some(amplitude) = attenuate(volume, heard, radius) else {return false};
+        return self.sound.as_mut().play_positioned(sound, amplitude, panning(heard), self.resources.as_mut());
+    }
+
+    /// Starts, adjusts and stops the looping sounds of every emitter the player can hear.
+    /// Objects emit the sound of their template; entities may also carry their own Emitter.
+    /// Called at the start of the player's turn, so the world is walked again for it.
+    pub fn update_soundscape(&mut self) {
+        if self.hearing(true).is_none() {
+            return;
+        }
+        let heard = &self.hearing.as_ref().unwrap().heard;
+        // An entity with several objects is heard once, from the loudest of them
+        let mut emitting: HashMap<EmitterSource, (String, f64, f64)> = HashMap::new();
+        let mut add = |source: EmitterSource, emitter: &Emitter, heard: Heard| {
+            if let Some(amplitude) = attenuate(emitter.volume, heard, emitter.radius) {
+                let louder = emitting.get(&source).map_or(true, |(_, other, _)| amplitude > *other);
+                if louder {
+                    emitting.insert(source, (emitter.sound.clone(), amplitude, panning(heard)));
+                }
+            }
+        };
+        for (tile, tile_heard) in heard.iter() {
+            let objects = self.world.objects_on(*tile);
+            for (i, object) in objects.iter().enumerate() {
+                if let Some(emitter) = &self.entities.template(object.template_id).emits {
+                    let source = match object.entity_id {
+                        Some(id) => EmitterSource::EntityObject(id, object.template_id),
+                        None => {
+                            let before = objects[..i].iter()
+                                .filter(|other| other.entity_id.is_none() && other.template_id == object.template_id)
+                                .count();
+                            EmitterSource::Object(tile.map, tile.tile as usize, object.template_id, before)
+                        }
+                    };
+                    add(source, emitter, *tile_heard);
+                }
+                if let Some(id) = object.entity_id {
+                    if let Some(emitter) = self.entities[id].get::<Emitter>() {
+                        add(EmitterSource::Entity(id), emitter, *tile_heard);
+                    }
+                }
+            }
+        }
+        let emitting = emitting.into_iter()
+            .map(|(source, (sound, amplitude, panning))| (source, sound, amplitude, panning)).collect();
+        self.sound.as_mut().set_emitters(emitting, self.resources.as_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heard(distance: usize, right: i32) -> Heard {
+        Heard {distance, right}
+    }
+
+    #[test]
+    fn falloff_reaches_nothing_past_the_radius() {
+        assert_eq!(attenuate(255, heard(0, 0), 4), Some(1.));
+        let amplitudes: Vec<f64> = (0..=4).map(|d| attenuate(255, heard(d, 0), 4).unwrap()).collect();
+        assert!(amplitudes.windows(2).all(|pair| pair[1] < pair[0]));
+        // One more step would be silent
+        assert!((amplitudes[4] - 1./5.).abs() < 1e-9);
+        assert_eq!(attenuate(255, heard(5, 0), 4), None);
+        assert_eq!(attenuate(255, heard(40, 0), 4), None);
+    }
+
+    #[test]
+    fn falloff_scales_with_volume() {
+        let full = attenuate(255, heard(2, 0), 8).unwrap();
+        let half = attenuate(51, heard(2, 0), 8).unwrap();
+        assert!((half*5. - full).abs() < 1e-9);
+    }
+
+    #[test]
+    fn panning_is_centred_at_the_source() {
+        assert_eq!(panning(heard(0, 0)), 0.5);
+        assert_eq!(panning(heard(3, 0)), 0.5);
+    }
+
+    #[test]
+    fn panning_stays_in_range() {
+        for distance in 1..6 {
+            for right in -8..=8 {
+                let pan = panning(heard(distance, right));
+                assert!((0. ..=1.).contains(&pan), "{} {} gave {}", distance, right, pan);
+            }
+        }
+        assert_eq!(panning(heard(2, 2)), 1.);
+        assert_eq!(panning(heard(2, -2)), 0.);
+    }
+}
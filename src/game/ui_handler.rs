@@ -2,7 +2,8 @@ use crate::common::{ResourceHandler, Id, ExtTree, UITile,TakeBox, NONETILE};
 use crate::main;
 use crate::ui::{UI, Poll, PollResult, Match, PollCandidate, Candidate};
 use crate::ui::widgets::*;
-use super::{EntityHandler, GameData, Traverser, UniqTile, MapHandler};
+use super::{EntityHandler, GameData, Traverser, UniqTile, MapHandler, MapID};
+use std::collections::HashSet;
 use serde::{Deserialize,Serialize};
 
 pub const PLAYER_VIEW_SIZE: (usize,usize) = (60,60);
@@ -27,15 +28,39 @@ impl UIHandler {
         }
     }
 
+    /// Without a terminal, for tests of game logic that does not draw anything
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        Self {
+            writable: TakeBox::none(),
+            static_base: Id::default(),
+            main_character_view: Id::default(),
+            log_screen: Id::default(),
+        }
+    }
+
     pub fn area_size(&self, widget: Id) -> Option<(usize,usize)> {
         let ui = self.writable.as_ref();
         Some(self.writable.as_ref().widget::<LOSArea>(widget).unwrap().dim())
     }
 
-    pub fn update_los(id: Id, trav: Traverser, data: &mut GameData) {
+    /// The maps in line of sight when it was last drawn. The drawing is kept in saves, unlike MapHandler's visible maps.
+    pub fn drawn_maps(&self) -> HashSet<MapID> {
+        match self.writable.as_ref().widget::<LOSArea>(self.main_character_view) {
+            Some(area) => area.visible_maps(),
+            None => HashSet::new(),
+        }
+    }
+
+    pub fn update_los(id: Id, trav: Traverser, radius: Option<usize>, data: &mut GameData) {
         let mut ui = data.ui.writable.take();
-        ui.mut_widget::<LOSArea>(id).unwrap().set(trav, data);
+        let visible = {
+            let widget = ui.mut_widget::<LOSArea>(id).unwrap();
+            widget.set(trav, radius, data);
+            widget.visible_maps()
+        };
         data.ui.writable.replace(ui);
+        data.world.set_visible(visible, data.updates.current_time);
     }
 
     /*
@@ -45,7 +70,7 @@ impl UIHandler {
     */
 
     pub fn log(&mut self, string: &str) {
-        self.writable.as_mut().mut_widget::<LineScroll>(self.log_screen).unwrap().extend(string);
+        self.writable.as_mut().mut_widget::<LinesScroll>(self.log_screen).unwrap().push(string);
     }
 
     pub fn poll(&mut self, poll: &Poll, resources: &mut ResourceHandler) -> PollResult {
@@ -93,6 +118,44 @@ impl UIHandler {
         self.log_screen = log_screen;
     }
     
+    /// Opens a conversation in front of the game view,
+    /// with text above a list of replies.
+    /// Returns the split between them, the text and the replies.
+    pub fn open_conversation(&mut self, text_speed: usize) -> (Id, Id, Id) {
+        let ui = self.writable.as_mut();
+        let root = ui.root();
+        let [split, text, replies] = ui.append_to::<Tabs>(root,
+            ExtTree((false, Aligned::new(WidgetBound {width: 60, height: 16}, (0.5,0.5)).into()), vec![
+                ExtTree((false, Border::default().into()), vec![
+                    ExtTree((true, Split::new(true, true, SplitType::AbsBelow(6)).into()), vec![
+                        ExtTree((true, LineScroll::new("", text_speed).into()), vec![]),
+                        ExtTree((true, Lines::new().into()), vec![])
+                    ])
+                ])
+            ])
+        )[..] else {panic!()};
+        ui.mut_widget::<Tabs>(root).unwrap().selected = ui.children(root)-1;
+        (split, text, replies)
+    }
+
+    pub fn show_conversation(&mut self, text: Id, replies: Id, string: &str, choices: Vec<String>) {
+        let ui = self.writable.as_mut();
+        ui.mut_widget::<LineScroll>(text).unwrap().change(string);
+        ui.mut_widget::<Lines>(replies).unwrap().set_lines(choices);
+    }
+
+    /// Sends keys to the replies instead of the text.
+    pub fn reply_mode(&mut self, split: Id, replying: bool) {
+        self.writable.as_mut().mut_widget::<Split>(split).unwrap().set_active(!replying);
+    }
+
+    pub fn close_conversation(&mut self) {
+        let ui = self.writable.as_mut();
+        let root = ui.root();
+        ui.mut_widget::<Tabs>(root).unwrap().selected = 0;
+        ui.remove_child::<Tabs>(root, -1);
+    }
+
     pub fn ui_tile(&self, tile: UniqTile, world: &MapHandler, actors: &EntityHandler) -> UITile {
         let objects = world.objects_on(tile);
         if objects.len() == 0 {
@@ -4,36 +4,67 @@ use std::cmp::Ordering;
 use serde::{Serialize,Deserialize};
 
 pub type Time = usize;
+pub type UpdateID = u64;
+
+// Simulated catch-up never reaches further back than this,
+// so a map left alone for a long time does not stall the game when it loads.
+const CATCH_UP_LIMIT: Time = 10000;
+
+/// What happens to the updates of an entity that was unloaded with its map
+/// once the map is loaded again.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum CatchUp {
+    /// Delayed by how long the map was unloaded, as if time stopped for the entity.
+    Freeze,
+    /// Overdue updates happen once, right away, in place of everything that was missed.
+    Summarize,
+    /// Overdue updates run at their original times, in order, until the entity reaches the present.
+    Simulate,
+}
 
 #[derive(Debug,Serialize,Deserialize)]
 pub struct Update {
     pub time: Time,
     pub behavior: Behavior,
+    #[serde(default)]
+    pub id: UpdateID,
 }
 
+// Updates at the same time happen in the order they were added.
 impl PartialEq for Update {
     fn eq(&self, other: &Self) -> bool {
-        self.time == other.time
+        self.time == other.time && self.id == other.id
     }
 } impl Eq for Update {}
 
 impl PartialOrd for Update {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.time.cmp(&other.time))
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Update {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.time.cmp(&other.time)
+        (self.time, self.id).cmp(&(other.time, other.id))
     }
 }
 
+/// Refers to one update, for cancelling or rescheduling it.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Hash,Serialize,Deserialize)]
+pub struct UpdateHandle {
+    pub entity: EntityID,
+    pub id: UpdateID,
+}
+
 #[derive(Serialize,Deserialize)]
 pub struct UpdateHandler {
-    pub next_update_id: u32,
+    pub next_update_id: UpdateID,
+    /// Never goes back, even while overdue updates are caught up.
     pub current_time: Time,
-    update_heap: CoupledHeap<EntityID, Update>
+    update_heap: CoupledHeap<EntityID, Update>,
+    // The time of the update being run, while it is earlier than current_time
+    #[serde(skip)]
+    behind: Option<Time>,
 }
 
 impl UpdateHandler {
@@ -42,23 +73,57 @@ impl UpdateHandler {
             current_time: 0,
             next_update_id: 0,
             update_heap: CoupledHeap::new(),
+            behind: None,
         }
     }
+
+    /// The time of the update being run. While an entity is being caught up
+    /// this is earlier than current_time, so that it keeps to its own timeline.
+    pub fn now(&self) -> Time {
+        self.behind.unwrap_or(self.current_time)
+    }
     
-    pub fn add_update(&mut self, time_until: Time, ent_id: EntityID, behavior: Behavior) {
-        let time_of_update = self.current_time+time_until;
+    /// Adds an update time_until after the update being run.
+    pub fn add_update(&mut self, time_until: Time, ent_id: EntityID, behavior: Behavior) -> UpdateHandle {
+        let time_of_update = self.now()+time_until;
+        let id = self.next_update_id;
+        self.next_update_id += 1;
         let update = Update {
             time: time_of_update,
             behavior: behavior,
+            id,
         };
         self.update_heap.push(ent_id, update);
+        UpdateHandle {entity: ent_id, id}
+    }
+
+    /// Returns the update if it had not happened yet.
+    pub fn cancel(&mut self, handle: UpdateHandle) -> Option<Update> {
+        return self.update_heap.remove_where(handle.entity, |update| update.id == handle.id);
+    }
+
+    /// Moves an update to happen time_until from now, after any others already at that time.
+    /// Returns the new handle of the update, or None if it has already happened or was cancelled.
+    pub fn reschedule(&mut self, handle: UpdateHandle, time_until: Time) -> Option<UpdateHandle> {
+        let update = self.cancel(handle)?;
+        Some(self.add_update(time_until, handle.entity, update.behavior))
+    }
+
+    /// The updates an entity has waiting, soonest first.
+    pub fn pending(&self, entity: EntityID) -> Vec<(UpdateHandle, &Update)> {
+        let mut updates: Vec<&Update> = self.update_heap.get(entity).iter().collect();
+        updates.sort();
+        return updates.into_iter().map(|update| (UpdateHandle {entity, id: update.id}, update)).collect();
     }
     
+    // Updates being caught up after a map loads are earlier than the current time,
+    // which stays where it is while they run.
     pub fn next(&mut self) -> Option<(EntityID, Update)> {
         let some_update = self.update_heap.pop();
         match some_update {
             Some(upd) => {
-                self.current_time = upd.1.time;
+                self.behind = if upd.1.time < self.current_time {Some(upd.1.time)} else {None};
+                self.current_time = self.current_time.max(upd.1.time);
                 Some(upd)
             },
             None => None,
@@ -69,7 +134,27 @@ impl UpdateHandler {
         self.update_heap.insert(entity, vec);
     }
 
+    /// Inserts the updates of an entity whose map was unloaded at `since`,
+    /// moving each overdue update according to the CatchUp of its behavior.
+    pub fn insert_caught_up(&mut self, entity: EntityID, mut vec: Vec<Update>, since: Time) {
+        let elapsed = self.current_time.saturating_sub(since);
+        let earliest = self.current_time.saturating_sub(CATCH_UP_LIMIT);
+        for update in vec.iter_mut() {
+            match update.behavior.catch_up() {
+                CatchUp::Freeze => update.time += elapsed,
+                CatchUp::Summarize => update.time = update.time.max(self.current_time),
+                CatchUp::Simulate => update.time = update.time.max(earliest),
+            }
+        }
+        // Different policies can reorder the updates; a sorted list is a valid heap again.
+        vec.sort();
+        self.insert(entity, vec);
+    }
+
     pub fn remove(&mut self, entity: EntityID) -> Vec<Update> {
+        if !self.update_heap.contains(entity) {
+            return Vec::new();
+        }
         return self.update_heap.remove(entity);
     }
     
@@ -79,3 +164,74 @@ impl UpdateHandler {
     
     pub fn len(&self) -> usize {self.update_heap.len()}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(time: Time, behavior: Behavior) -> Update {
+        Update {time, behavior, id: time as UpdateID}
+    }
+
+    #[test]
+    fn insert_caught_up() {
+        let mut updates = UpdateHandler::new();
+        updates.current_time = 50000;
+        updates.insert_caught_up(0, vec![
+            update(1100, Behavior::Player),
+            update(1200, Behavior::PlayerStartingDraw),
+            update(1300, Behavior::EffectTick),
+            update(60000, Behavior::EffectTick),
+        ], 1000);
+
+        // Simulate runs from no further back than the limit
+        let (_, first) = updates.next().unwrap();
+        assert_eq!(first.behavior, Behavior::EffectTick);
+        assert_eq!(first.time, 50000-CATCH_UP_LIMIT);
+        assert_eq!(updates.current_time, 50000);
+        assert_eq!(updates.now(), 50000-CATCH_UP_LIMIT);
+        // Summarize happens right away
+        let (_, second) = updates.next().unwrap();
+        assert_eq!(second.behavior, Behavior::PlayerStartingDraw);
+        assert_eq!(second.time, 50000);
+        assert_eq!(updates.now(), 50000);
+        // Freeze is delayed by how long the map was unloaded
+        let (_, third) = updates.next().unwrap();
+        assert_eq!(third.behavior, Behavior::Player);
+        assert_eq!(third.time, 1100+49000);
+        // Updates that were not overdue are left alone
+        let (_, fourth) = updates.next().unwrap();
+        assert_eq!(fourth.time, 60000);
+        assert_eq!(updates.current_time, 60000);
+        assert!(updates.next().is_none());
+    }
+
+    #[test]
+    fn reschedule_goes_after_updates_at_the_same_time() {
+        let mut updates = UpdateHandler::new();
+        let first = updates.add_update(10, 0, Behavior::Player);
+        let second = updates.add_update(20, 1, Behavior::EffectTick);
+        let moved = updates.reschedule(first, 20).unwrap();
+        assert_ne!(moved.id, first.id);
+        assert_eq!(updates.reschedule(first, 5), None);
+        assert!(updates.cancel(first).is_none());
+        let (entity, update) = updates.next().unwrap();
+        assert_eq!((entity, update.id), (second.entity, second.id));
+        let (entity, update) = updates.next().unwrap();
+        assert_eq!((entity, update.id, update.time), (0, moved.id, 20));
+        assert!(updates.next().is_none());
+    }
+
+    #[test]
+    fn caught_up_updates_add_updates_in_the_past() {
+        let mut updates = UpdateHandler::new();
+        updates.current_time = 500;
+        updates.insert_caught_up(0, vec![update(100, Behavior::EffectTick)], 0);
+        updates.next().unwrap();
+        let handle = updates.add_update(10, 0, Behavior::EffectTick);
+        let (_, pending) = updates.pending(0)[0];
+        assert_eq!(pending.id, handle.id);
+        assert_eq!(pending.time, 110);
+        assert_eq!(updates.current_time, 500);
+    }
+}
@@ -14,7 +14,7 @@ fn main() {
                 l.push("holy fucking shit that was a little overkill but what do you expect from THE BEST??!?!?!?!?!?!"); l.into()}), vec![]));
             let [widget] = v[..] else {panic!()};
             return widget;
-        }) {println!(crate::translate!(start_err), e)}
+        }) {println!("{}", crate::tr!("start_err", e))}
     } else if let Some(_) = option_env!("maptest") {
         // For testing the appearance and functionality of maps.
         // This will find the first available square in the map to place
@@ -22,7 +22,16 @@ fn main() {
         let args: Vec<String> = std::env::args().skip(1).collect();
         let args2: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
         if let Err(e) = state_machine::map_test(&args2) {
-            println!(crate::translate!(start_err), e)
+            println!("{}", crate::tr!("start_err", e))
+        }
+    } else if let Some(_) = option_env!("mapgraph") {
+        // For seeing how the maps of a save are connected.
+        // Takes the name of a save folder, and optionally a file to write
+        // the DOT graph to instead of printing it.
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let args2: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        if let Err(e) = state_machine::world_graph(&args2) {
+            println!("{}", crate::tr!("start_err", e))
         }
     } else {
         let skip;
@@ -31,7 +40,7 @@ fn main() {
         } else {
             skip = false;
         }
-        if let Err(e) = state_machine::normal_start(skip) {println!(crate::translate!(start_err), e)}
+        if let Err(e) = state_machine::normal_start(skip) {println!("{}", crate::tr!("start_err", e))}
     }
 }
 
@@ -40,5 +49,4 @@ mod common;
 mod game;
 mod filesystem;
 mod translation_english;
-//mod traducción_español;
 pub use common::*;
@@ -1,11 +1,12 @@
 
 use crate::ui::{Candidate, Match, Poll, UI};
 use crate::ui::widgets::*;
-use crate::filesystem::get_resources;
+use crate::filesystem::{get_resources, languages, set_language, key_name, keys_of, BINDABLE, Setting, SettingKind, SETTINGS};
 use crate::game::GameData;
-use crate::common::{Style, Rgb, Id, ExtTree, ResourceHandler, transition_length, transition_default, RemoveVec, SoundManager, TakeBox};
-use crate::{errstr, err};
+use crate::common::{Style, Rgb, Id, ExtTree, ResourceHandler, transition_length, transition_default, RemoveVec, SoundManager, TakeBox, ColorDepth};
+use crate::{errstr, err, tr};
 use std::error::Error;
+use serde_json::Value;
 
 /********************************************************/
 // START TYPES
@@ -15,7 +16,7 @@ pub fn normal_start(skip_intro: bool) -> Result<(), String> {
     let (paths, options, debug) = get_resources()?;
     let mut handler = ResourceHandler::new(paths, options, debug);
     handler.init();
-    println!(crate::translate!(loading_text));
+    println!("{}", crate::tr!("loading_text"));
     let sound = SoundManager::new(handler.static_sounds(), &mut handler);
     let mut ui = UI::new(&mut handler);
     ui.new_context(ExtTree((false, Tabs::default().into()), vec![]));
@@ -29,7 +30,7 @@ pub fn map_test(maps: &[&str]) -> Result<(), String> {
     let (paths, options, debug) = get_resources()?;
     let mut handler = ResourceHandler::new(paths, options, debug);
     handler.init();
-    println!(crate::translate!(loading_text));
+    println!("{}", crate::tr!("loading_text"));
     let sound = SoundManager::new(handler.static_sounds(), &mut handler);
     let mut ui = UI::new(&mut handler);
     ui.new_context(ExtTree((false, Tabs::default().into()), vec![]));
@@ -53,6 +54,22 @@ pub fn map_test(maps: &[&str]) -> Result<(), String> {
     Ok(())
 }
 
+pub fn world_graph(args: &[&str]) -> Result<(), String> {
+    let (paths, options, debug) = get_resources()?;
+    let mut handler = ResourceHandler::new(paths, options, debug);
+    let save_name = args.get(0).ok_or(errstr!("a save folder must be given"))?;
+    if !handler.path.saves.join(save_name).exists() {
+        return Err(errstr!(format!("there is no save named {}", save_name)));
+    }
+    err!(handler.attach_to_game(save_name))?;
+    let dot = GameData::world_graph_from_save(&mut handler).ok_or(errstr!("the save could not be read"))?;
+    match args.get(1) {
+        Some(path) => err!(std::fs::write(path, dot))?,
+        None => println!("{}", dot),
+    }
+    Ok(())
+}
+
 pub fn widget_test<Func>(f: Func) -> Result<(), String> where 
 Func: FnOnce(&mut UI, &mut ResourceHandler) -> Id {
     let (paths, options, debug) = get_resources()?;
@@ -182,45 +199,70 @@ impl PersistentComponents {
     }
 }
 
-fn change_options(options_widgets: [Id; 3], ui: &mut UI, resources: &mut ResourceHandler, sound: &mut SoundManager) {
-    let [options_base, volume, text_speed] = options_widgets;
-    let poll = Poll::from([
-        (volume, Candidate::Left),
-        (volume, Candidate::Right),
-        (text_speed, Candidate::Left),
-        (text_speed, Candidate::Right),
-        (options_base, Candidate::Exit),
+fn change_options(menu: &OptionsMenu, ui: &mut UI, resources: &mut ResourceHandler, sound: &mut SoundManager) {
+    let language_list = languages(&resources.path);
+    let mut poll = Poll::from([
+        (menu.screen, Candidate::Exit),
     ]);
+    for (setting, _, value) in &menu.rows {
+        let candidates: &[Candidate] = match setting.kind {
+            SettingKind::Keys => &[Candidate::Enter],
+            SettingKind::Toggle {..} => &[Candidate::Left, Candidate::Right, Candidate::Enter],
+            _ => &[Candidate::Left, Candidate::Right],
+        };
+        poll.extend(candidates.iter().map(|candidate| (*value, *candidate)));
+    }
     let prev_selected = ui.widget::<Tabs>(ui.root()).unwrap().selected;
-    ui.mut_widget::<Tabs>(ui.root()).unwrap().selected = ui.child_num(ui.root(), options_base);
+    ui.mut_widget::<Tabs>(ui.root()).unwrap().selected = ui.child_num(ui.root(), menu.screen);
     loop {
-        match ui.poll_from(&poll, resources) {
-            (widget, Match::Standard(Candidate::Left)) if widget == volume =>
-            if let Some(mut widget) = ui.mut_widget::<ProgressBar>(volume) {
-                if widget.amt > 5 {widget.amt -= 5;}
-                else {widget.amt = 0;}
-                sound.set_background_volume(widget.amt, resources, transition_default());
-                resources.options.volume = widget.amt;
+        let (widget, candidate) = match ui.poll_from(&poll, resources) {
+            (widget, Match::Standard(Candidate::Exit)) if widget == menu.screen => break,
+            (widget, Match::Standard(candidate)) => (widget, candidate),
+            _ => {
+                resources.err(&errstr!(tr!("options_bad_key")));
+                continue;
+            }
+        };
+        let Some((setting, _, _)) = menu.rows.iter().find(|(_, _, value)| *value == widget) else {
+            resources.err(&errstr!(tr!("options_bad_key")));
+            continue;
+        };
+        let right = candidate != Candidate::Left;
+        let current = resources.options.get(setting.field);
+        let next = match &setting.kind {
+            SettingKind::Keys => {
+                change_keys(ui, resources);
+                ui.mut_widget::<Tabs>(ui.root()).unwrap().selected = ui.child_num(ui.root(), menu.screen);
+                continue;
+            },
+            SettingKind::Language => {
+                // The fallback language is always in the list
+                let len = language_list.len();
+                let i = language_list.iter().position(|l| Some(l.as_str()) == current.as_str()).unwrap_or(0);
+                Value::from(language_list[if right {(i+1)%len} else {(i+len-1)%len}].clone())
             },
-            (widget, Match::Standard(Candidate::Right)) if widget == volume =>
-            if let Some(mut widget) = ui.mut_widget::<ProgressBar>(volume) {
-                if widget.amt < 250 {widget.amt += 5;}
-                else {widget.amt = 255;}
-                sound.set_background_volume(widget.amt, resources, transition_default());
-                resources.options.volume = widget.amt;
+            kind => kind.shift(&current, right),
+        };
+        if let Err(e) = resources.options.set(setting.field, &next) {
+            resources.err(&e);
+            continue;
+        }
+        show_setting(setting, widget, ui, resources);
+        match setting.field {
+            "volume" | "music_volume" | "ambience_volume" | "effects_volume" => {
+                sound.set_volumes(resources, transition_default());
             },
-            (widget, Match::Standard(Candidate::Left)) if widget == text_speed =>
-            if let Some(mut widget) = ui.mut_widget::<Choice>(text_speed) {
-                widget.shift_left();
-                resources.options.text_speed = widget.selected as u8;
+            "audio" => {
+                sound.set_enabled(resources.options.audio, resources);
             },
-            (widget, Match::Standard(Candidate::Right)) if widget == text_speed => 
-            if let Some(mut widget) = ui.mut_widget::<Choice>(text_speed) {
-                widget.shift_right();
-                resources.options.text_speed = widget.selected as u8;
+            "color_mode" => {
+                ui.set_colors(ColorDepth::from_option(resources.options.color_mode));
             },
-            (widget, Match::Standard(Candidate::Exit)) if widget == options_base => break,
-            _ => resources.err(&errstr!(crate::translate!(options_bad_key)))
+            "language" => match set_language(&resources.path, &resources.options.language) {
+                Ok(()) => relabel_options_menu(menu, ui),
+                Err(e) => resources.err(&e),
+            },
+            _ => ()
         }
     }
     resources.save_options();
@@ -230,26 +272,29 @@ fn change_options(options_widgets: [Id; 3], ui: &mut UI, resources: &mut Resourc
 pub fn spin(res: ResourceHandler, ui: UI, sound: SoundManager, skip_intro: bool) -> Result<(), Box<dyn Error>> {
     let mut components = PersistentComponents::new(res,ui,sound);
     let [menu_base, menu_choice] = add_main_menu(components.ui.as_mut(), components.resources.as_mut());
-    let options_widgets = add_options_menu(components.ui.as_mut(), components.resources.as_ref());
+    let options_menu = add_options_menu(components.ui.as_mut(), components.resources.as_ref());
 
     let menu_poll = Poll::from([
         (menu_choice, Candidate::Select),
     ]);
 
     components.from_game();
-    components.sound.as_mut().background(&crate::translate!("rain"), components.resources.as_mut());
+    components.sound.as_mut().background("rain", components.resources.as_mut());
     
     // State loop (main menu->game->back and such, NOT framerate loop (in UI) or game loop
     // (in GameData))
     loop {
         components.from_game();
+        // Relabelled every time, since the language may have changed in the options
         match components.ui.as_mut().mut_widget::<TitleBottom>(menu_choice) {
             Some(mut widget) => {
-                widget.set(0, match components.game {
-                    Some(_) => crate::translate!("Continue").to_string(),
-                    None => crate::translate!("Start").to_string()
-                });
-            }, _=>components.resources.as_mut().err(&errstr!(crate::translate!(main_menu_no_match)))
+                for (i, entry) in MAIN_MENU_ENTRIES.iter().enumerate() {
+                    widget.set(i, tr!(entry));
+                }
+                if components.game.is_some() {
+                    widget.set(0, tr!("menu_continue"));
+                }
+            }, _=>components.resources.as_mut().err(&errstr!(tr!("main_menu_no_match")))
         };
         match components.ui.as_mut().poll_from(&menu_poll, components.resources.as_mut()) {
             (_, Match::Selection1D(0)) => {
@@ -295,10 +340,10 @@ pub fn spin(res: ResourceHandler, ui: UI, sound: SoundManager, skip_intro: bool)
                 ui.remove_child::<Tabs>(root, -1);
             },
             (_, Match::Selection1D(2)) => {
-                change_options(options_widgets, components.ui.as_mut(), components.resources.as_mut(), components.sound.as_mut())
+                change_options(&options_menu, components.ui.as_mut(), components.resources.as_mut(), components.sound.as_mut())
             },
             (_, Match::Selection1D(3)) => break,
-            _ => components.resources.as_mut().err(&errstr!(crate::translate!(main_menu_bad_key))),
+            _ => components.resources.as_mut().err(&errstr!(tr!("main_menu_bad_key"))),
         }
     }
     
@@ -314,9 +359,11 @@ pub fn spin(res: ResourceHandler, ui: UI, sound: SoundManager, skip_intro: bool)
 // COMPONENT GENERATION
 /********************************************************/
 
+const MAIN_MENU_ENTRIES: [&str; 4] = ["menu_start", "menu_load", "menu_options", "menu_exit"];
+
 /// Returns (id of base display window, id of lines widget that receives input)
 fn add_main_menu(ui: &mut UI, resources: &mut ResourceHandler) -> [Id; 2] {
-    let title_top = TitleTop::from_file(concat!(crate::translate!("title"),".txt"), (0.5, 0.3), resources);
+    let title_top = TitleTop::from_file("title.txt", (0.5, 0.3), resources);
     let (hash,step) = (title_top.hash(), title_top.step());
     let vars = ui.append_to::<Tabs>(ui.root(), 
         ExtTree((true, 
@@ -334,13 +381,8 @@ fn add_main_menu(ui: &mut UI, resources: &mut ResourceHandler) -> [Id; 2] {
             vec![
                 ExtTree((false, title_top.into()), vec![]),
                 ExtTree((true, 
-                TitleBottom::from(vec!(
-                    crate::translate!("Start").to_string(),
-                    crate::translate!("Load").to_string(),
-                    crate::translate!("Options").to_string(),
-                    crate::translate!("Exit").to_string(),
-                ), hash, step, 
-                crate::translate!("city_background.txt"), resources).into()), vec![]),
+                TitleBottom::from(MAIN_MENU_ENTRIES.iter().map(|entry| tr!(entry)).collect(), hash, step, 
+                "city_background.txt", resources).into()), vec![]),
             ])
         ])
     );
@@ -352,49 +394,206 @@ pub fn load_screen(resources: &mut ResourceHandler) -> WidgetEnum {
     return Lines::from_vec(resources.list_saves().unwrap()).into();
 }
 
-fn add_options_menu(ui: &mut UI, resources: &ResourceHandler) -> [Id; 3] {
+/// The options menu, with the label and value widgets of each setting shown in it
+struct OptionsMenu {
+    screen: Id,
+    rows: Vec<(&'static Setting, Id, Id)>,
+}
+
+fn add_options_menu(ui: &mut UI, resources: &ResourceHandler) -> OptionsMenu {
+    let shown: Vec<&'static Setting> = SETTINGS.iter().filter(|setting| setting.label.is_some()).collect();
+    let rows = shown.iter().map(|setting| ExtTree((false,
+        Split::new(
+            false,
+            false,
+            SplitType::AbsBelow(30),
+        ).into()), vec![
+            ExtTree((true, Line {string: tr!(setting.label.unwrap_or(""))}.into()), vec![]),
+            ExtTree((true, setting_widget(setting, resources)), vec![])
+        ])).collect();
     let vars = ui.append_to::<Tabs>(ui.root(),
-        ExtTree((true, LinesChildren::from_vec(vec![1, 1]).into()), vec![
-            ExtTree((false,
-            Split::new(
-                false,
-                false,
-                SplitType::AbsBelow(30),
-            ).into()), vec![
-                ExtTree((false, Line {string: crate::translate!("Volume:").to_string()}.into()), vec![]),
-                ExtTree((true, ProgressBar {amt: resources.options.volume}.into()), vec![])
-            ]),
-            ExtTree((false,
-            Split::new(
-                false,
-                false,
-                SplitType::AbsBelow(30),
-            ).into()), vec![
-                ExtTree((false, Line {string: crate::translate!("Text Speed:").to_string()}.into()), vec![]),
-                ExtTree((true,
-                Choice::from_vec(vec![
-                    // If you add more of these, make sure to release the restriction in reading in options.rs
-                    // I don't feel like fixing this rn
-                    // TODO: fix this
-                    crate::translate!("Slow").to_string(),
-                    crate::translate!("Medium").to_string(),
-                    crate::translate!("Fast").to_string(),
-                    crate::translate!("Instant").to_string()
-                ], resources.options.text_speed.into(), false).into()), vec![])
-            ])
-        ])
+        ExtTree((true, LinesChildren::from_vec(vec![1; shown.len()]).into()), rows)
     );
-    let [screen, volume, text_speed] = vars[..] else {panic!()};
-    return [screen, volume, text_speed];
+    let rows = shown.into_iter().zip(vars[1..].chunks(2))
+        .map(|(setting, ids)| (setting, ids[0], ids[1])).collect();
+    return OptionsMenu {screen: vars[0], rows};
+}
+
+fn choice_names(choices: &[&str]) -> Vec<String> {
+    choices.iter().map(|choice| tr!(choice)).collect()
+}
+
+/// Sliders are shown on a bar from 0 to 255
+fn slider_amount(value: &Value, min: i64, max: i64) -> u8 {
+    let value = value.as_i64().unwrap_or(min);
+    ((value-min) as f64/(max-min) as f64*255.).round() as u8
+}
+
+fn setting_widget(setting: &Setting, resources: &ResourceHandler) -> WidgetEnum {
+    let value = resources.options.get(setting.field);
+    match &setting.kind {
+        SettingKind::Slider {min, max, ..} => ProgressBar {amt: slider_amount(&value, *min, *max)}.into(),
+        SettingKind::Choice {choices, ..} =>
+            Choice::from_vec(choice_names(choices), value.as_u64().unwrap_or(0) as usize, false).into(),
+        SettingKind::Language => {
+            let language_list = languages(&resources.path);
+            let selected = language_list.iter().position(|l| Some(l.as_str()) == value.as_str()).unwrap_or(0);
+            Choice::from_vec(language_list, selected, true).into()
+        },
+        SettingKind::Toggle {..} =>
+            Choice::from_vec(choice_names(&["toggle_off", "toggle_on"]), value.as_bool().unwrap_or(false) as usize, true).into(),
+        SettingKind::Keys => Line {string: tr!("change_keys")}.into(),
+    }
+}
+
+/// Moves the value widget of a setting to what is in the options
+fn show_setting(setting: &Setting, widget: Id, ui: &mut UI, resources: &ResourceHandler) {
+    let value = resources.options.get(setting.field);
+    match &setting.kind {
+        SettingKind::Slider {min, max, ..} => if let Some(mut widget) = ui.mut_widget::<ProgressBar>(widget) {
+            widget.amt = slider_amount(&value, *min, *max);
+        },
+        SettingKind::Choice {..} | SettingKind::Toggle {..} => if let Some(mut widget) = ui.mut_widget::<Choice>(widget) {
+            widget.selected = match value {
+                Value::Bool(on) => on as usize,
+                other => other.as_u64().unwrap_or(0) as usize,
+            };
+        },
+        SettingKind::Language => {
+            let language_list = languages(&resources.path);
+            if let Some(mut widget) = ui.mut_widget::<Choice>(widget) {
+                widget.selected = language_list.iter().position(|l| Some(l.as_str()) == value.as_str()).unwrap_or(0);
+            }
+        },
+        SettingKind::Keys => (),
+    }
+}
+
+/// Puts the options menu into the current language
+fn relabel_options_menu(menu: &OptionsMenu, ui: &mut UI) {
+    for (setting, label, value) in &menu.rows {
+        if let Some(mut widget) = ui.mut_widget::<Line>(*label) {
+            widget.string = tr!(setting.label.unwrap_or(""));
+        }
+        match &setting.kind {
+            SettingKind::Choice {choices, ..} => if let Some(mut widget) = ui.mut_widget::<Choice>(*value) {
+                widget.set_lines(choice_names(choices));
+            },
+            SettingKind::Toggle {..} => if let Some(mut widget) = ui.mut_widget::<Choice>(*value) {
+                widget.set_lines(choice_names(&["toggle_off", "toggle_on"]));
+            },
+            SettingKind::Keys => if let Some(mut widget) = ui.mut_widget::<Line>(*value) {
+                widget.string = tr!("change_keys");
+            },
+            _ => ()
+        }
+    }
+}
+
+/// Candidates are named in keys.json the same in every language, so they are shown through their own keys
+fn candidate_label(name: &str) -> String {
+    tr!(&format!("candidate_{}", name))
+}
+
+fn key_line(name: &str, keys: &[String]) -> String {
+    format!("{:<12}{}", candidate_label(name), keys.join(", "))
+}
+
+/// Lists every candidate with the keys bound to it.
+/// Selecting one waits for a key press, which is added to the candidate,
+/// or taken from it if the candidate already had it.
+/// A key bound to another candidate is only moved if it is pressed twice.
+/// Exit cancels waiting without changing anything, so keys bound to it cannot be captured.
+fn change_keys(ui: &mut UI, resources: &mut ResourceHandler) {
+    let root = ui.root();
+    let lines = BINDABLE.iter().map(|(name, candidate)| key_line(name, &keys_of(&resources.options.keys, *candidate))).collect();
+    let [screen, list, status] = ui.append_to::<Tabs>(root,
+        ExtTree((true, Split::new(true, true, SplitType::AbsBelow(1)).into()), vec![
+            ExtTree((true, Lines::from_vec(lines).into()), vec![]),
+            ExtTree((true, Line {string: tr!("keys_help")}.into()), vec![])
+        ])
+    )[..] else {panic!()};
+    ui.mut_widget::<Tabs>(root).unwrap().selected = ui.child_num(root, screen);
+    let choose = Poll::from([
+        (list, Candidate::Select),
+        (list, Candidate::Exit),
+    ]);
+    let capture = Poll::from([
+        (list, Candidate::AnyKey),
+        (list, Candidate::Exit),
+    ]);
+    let mut changed = false;
+    loop {
+        let n = match ui.poll_from(&choose, resources) {
+            (_, Match::Selection1D(n)) => n as usize,
+            (_, Match::Standard(Candidate::Exit)) => break,
+            _ => {
+                resources.err(&errstr!(tr!("options_bad_key")));
+                continue;
+            }
+        };
+        let Some((name, candidate)) = BINDABLE.get(n).copied() else {continue};
+        ui.mut_widget::<Line>(status).unwrap().string = tr!("keys_press", candidate_label(name));
+        let mut pending = None;
+        let message = loop {
+            let key = match ui.poll_from(&capture, resources) {
+                (_, Match::Key(key)) => key,
+                (_, Match::Standard(Candidate::Exit)) => break tr!("keys_cancelled"),
+                _ => continue,
+            };
+            let Some(shown) = key_name(&key) else {
+                break tr!("keys_unsupported");
+            };
+            match resources.options.keys.get(&key).copied() {
+                None => {
+                    resources.options.keys.insert(key, candidate);
+                    break tr!("keys_added", shown, candidate_label(name));
+                },
+                Some(bound) if bound == candidate => {
+                    if keys_of(&resources.options.keys, candidate).len() <= 1 {
+                        break tr!("keys_last", candidate_label(name));
+                    }
+                    resources.options.keys.remove(&key);
+                    break tr!("keys_removed", shown, candidate_label(name));
+                },
+                Some(bound) if pending == Some(key) => {
+                    if keys_of(&resources.options.keys, bound).len() <= 1 {
+                        break tr!("keys_last", candidate_label(bound_name(bound)));
+                    }
+                    resources.options.keys.insert(key, candidate);
+                    break tr!("keys_added", shown, candidate_label(name));
+                },
+                Some(bound) => {
+                    pending = Some(key);
+                    ui.mut_widget::<Line>(status).unwrap().string = tr!("keys_conflict", shown, candidate_label(bound_name(bound)));
+                }
+            }
+        };
+        changed = true;
+        ui.mut_widget::<Line>(status).unwrap().string = message;
+        let mut widget = ui.mut_widget::<Lines>(list).unwrap();
+        for (i, (name, candidate)) in BINDABLE.iter().enumerate() {
+            widget.set(i, key_line(name, &keys_of(&resources.options.keys, *candidate)));
+        }
+    }
+    if changed {
+        resources.save_keys();
+    }
+    ui.remove_child::<Tabs>(root, -1);
+}
+
+fn bound_name(candidate: Candidate) -> &'static str {
+    BINDABLE.iter().find(|(_, bound)| *bound == candidate).map_or("", |(name, _)| *name)
 }
 
 fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut ResourceHandler) -> bool {
-    sound.background(crate::translate!("rain_inside"), resources);
-    let base_volume = resources.options.volume;
+    sound.background("rain_inside", resources);
+    // Fades only the music; the options decide how loud that is
+    let base_volume = u8::MAX;
     let [top, split,text,input] = ui.append_to::<Tabs>(ui.root(),
         ExtTree((true, Aligned::new(WidgetBound {width: 27, height: 3}, (0.5,0.5)).into()), vec![
             ExtTree((true, Split::new(true, false, SplitType::AbsBelow(1)).into()), vec![
-                ExtTree((true, LineScroll::new(crate::translate!(intro_text_1), resources.options.text_speed as usize).finished().into()), vec![]),
+                ExtTree((true, LineScroll::new(&crate::tr!("intro_text_1"), resources.options.text_speed as usize).finished().into()), vec![]),
                 ExtTree((true, TextInput::new(20).into()), vec![])
             ])
         ])
@@ -415,7 +614,7 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     loop {
         let result = ui.poll_from(&input_send_or_return, resources);
         if result == (input, Match::Standard(Candidate::Exit)) {
-            sound.background(crate::translate!("rain"), resources);
+            sound.background("rain", resources);
             ui.mut_widget::<Tabs>(ui.root()).unwrap().selected = prev_selected;
             ui.remove_child::<Tabs>(ui.root(), -1);
             return false;
@@ -427,7 +626,7 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     sound.set_background_volume(base_volume/6*5, resources, transition_length(1000));
     ui.mut_widget::<TextInput>(input).unwrap().set("");
     ui.mut_widget::<Split>(split).unwrap().set_active(true);
-    ui.mut_widget::<LineScroll>(text).unwrap().change(crate::translate!(intro_text_2));
+    ui.mut_widget::<LineScroll>(text).unwrap().change(&crate::tr!("intro_text_2"));
     ui.poll_from(&text_finish, resources);
     ui.mut_widget::<Split>(split).unwrap().set_active(false);
     loop {
@@ -441,16 +640,16 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     let input_send = Poll::from([
         (input, Candidate::Enter)
     ]);
-    ui.mut_widget::<LineScroll>(text).unwrap().change(crate::translate!(intro_text_3));
+    ui.mut_widget::<LineScroll>(text).unwrap().change(&crate::tr!("intro_text_3"));
     sound.set_background_volume(base_volume/6*3, resources, transition_length(2000));
     ui.poll_from(&text_finish, resources);
     ui.poll_from(&input_send, resources);
-    ui.mut_widget::<LineScroll>(text).unwrap().change(crate::translate!(intro_text_4));
+    ui.mut_widget::<LineScroll>(text).unwrap().change(&crate::tr!("intro_text_4"));
     ui.poll_from(&text_finish, resources);
     ui.poll_from(&input_send, resources);
     sound.set_background_volume(0, resources, transition_default());
     let text2 = LineScroll::new(
-        crate::translate!(intro_text_5), 
+        &crate::tr!("intro_text_5"), 
         resources.options.text_speed as usize).with_style(Style::from_fg(Rgb(255,0,0)).bold()
     );
     let len = text2.len();
@@ -470,7 +669,7 @@ fn intro_cinematic(ui: &mut UI, sound: &mut SoundManager, resources: &mut Resour
     {
     let mut widget = ui.mut_widget::<LineScroll>(text).unwrap();
     widget.set_style(Style::default());
-    widget.change(crate::translate!(intro_text_6));
+    widget.change(&crate::tr!("intro_text_6"));
     }
     ui.poll_from(&text_finish, resources);
     ui.poll_from(&input_send, resources);
@@ -2,7 +2,7 @@ pub mod widgets;
 mod terminal_base;
 mod ui;
 
-pub use ui::{UI, Poll, WidgetBuffer, WidgetBound, PollCandidate, PollResult, Match, Candidate, Event, RawKey};
+pub use ui::{UI, WidgetData, Poll, WidgetBuffer, WidgetBound, PollCandidate, PollResult, Match, Candidate, Event, RawKey};
 use terminal_base::Terminal;
 use widgets::Widget;
 pub use widgets::WidgetEnum;
@@ -1,5 +1,6 @@
-use crate::common::{UIResources, Style};
+use crate::common::{UIResources, Style, Rgb, ColorDepth, ansi_color};
 
+use std::collections::HashMap;
 use std::io;
 use std::io::Write;
 use std::time::Duration;
@@ -9,20 +10,77 @@ use crossterm::event::{
     KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
 };
 use crossterm::{
-    cursor::{MoveTo, Hide, Show},
+    cursor::{MoveTo, MoveRight, Hide, Show},
     event::{
         read, poll, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
         EnableFocusChange, EnableMouseCapture, Event,
     },
     execute, queue,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, size},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, size,
+        BeginSynchronizedUpdate, EndSynchronizedUpdate, Clear, ClearType,
+    },
     style,
 };
 
+const BLANK: (char, Style) = (' ', Style { fg: None, bg: None, bold: false, ital: false, reverse: false });
+
+/// One thing written to the terminal while presenting a frame
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Draw {
+    /// Blanks the whole screen
+    Clear,
+    MoveTo(u16, u16),
+    MoveRight(u16),
+    Print(char, Style),
+}
+
+/// What has to be written to change a screen showing front into back, for a frame width columns wide,
+/// with the cursor starting at cursor if that is known. A front of a different size is drawn over in full.
+/// Also returns where the cursor is left.
+fn diff(front: &[(char, Style)], back: &[(char, Style)], width: u16, mut cursor: Option<(u16, u16)>)
+-> (Vec<Draw>, Option<(u16, u16)>) {
+    let mut draws = Vec::new();
+    let full = front.len() != back.len();
+    if full {
+        draws.push(Draw::Clear);
+        cursor = None;
+    }
+    for (i, &(ch, style)) in back.iter().enumerate() {
+        if !full && front[i] == (ch, style) {
+            continue;
+        }
+        let coord = ((i % width as usize) as u16, (i / width as usize) as u16);
+        // The shortest sequence which gets the cursor there
+        match cursor {
+            Some(at) if at == coord => {},
+            Some((x, y)) if y == coord.1 && x < coord.0 => draws.push(Draw::MoveRight(coord.0 - x)),
+            _ => draws.push(Draw::MoveTo(coord.0, coord.1)),
+        }
+        draws.push(Draw::Print(ch, style));
+        // Printing in the last column may or may not wrap
+        cursor = if coord.0+1 < width {Some((coord.0+1, coord.1))} else {None};
+    }
+    (draws, cursor)
+}
+
+/// Frames are drawn into a back buffer with put, and present only writes
+/// the cells which differ from what is already on the screen.
 pub struct Terminal {
     out: io::Stdout,
     cur_style: Style,
     supports_keyboard_enhancement: bool,
+    supports_synchronized_output: bool,
+    /// What is on the screen, row by row. Empty when it is not known.
+    front: Vec<(char, Style)>,
+    back: Vec<(char, Style)>,
+    /// Size of the frame being drawn, as (columns, rows)
+    frame: (u16, u16),
+    /// Where the next character will be printed, if known
+    cursor: Option<(u16, u16)>,
+    colors: ColorDepth,
+    /// Palette indices of colors already matched for colors
+    ansi: HashMap<Rgb, u8>,
 }
 
 impl Terminal {
@@ -37,10 +95,23 @@ impl Terminal {
             Ok(true)
         );
 
+        // Terminals ignore modes they do not know, except for these
+        let supports_synchronized_output = !matches!(
+            std::env::var("TERM").as_deref(),
+            Ok("linux") | Ok("dumb")
+        );
+
         let mut t = Terminal {
             out: stdout,
-            cur_style: Style { fg: None, bg: None, bold: false, ital: false, reverse: false },
+            cur_style: BLANK.1,
             supports_keyboard_enhancement: supports_keyboard_enhancement,
+            supports_synchronized_output: supports_synchronized_output,
+            front: Vec::new(),
+            back: Vec::new(),
+            frame: (0, 0),
+            cursor: None,
+            colors: ColorDepth::from_option(res.options.color_mode),
+            ansi: HashMap::new(),
         };
         
         res.early_choke(err!(enable_raw_mode()));
@@ -94,11 +165,137 @@ impl Terminal {
     pub fn wchar(&mut self, thing: char, res: &mut UIResources) {
         res.eat(err!(queue!(self.out, style::Print(thing))));
     }
+
+    /// Starts a new frame of the given size, blank until put into.
+    /// A frame of a different size than the last is drawn in full.
+    pub fn begin_frame(&mut self, width: u16, height: u16) {
+        if self.frame != (width, height) {
+            self.frame = (width, height);
+            self.invalidate();
+        }
+        self.back.clear();
+        self.back.resize(width as usize * height as usize, BLANK);
+    }
+
+    pub fn put(&mut self, coord: (u16, u16), thing: char, style: Style) {
+        let index = coord.1 as usize * self.frame.0 as usize + coord.0 as usize;
+        if let Some(cell) = self.back.get_mut(index) {
+            *cell = (thing, style);
+        }
+    }
+
+    /// Forgets what is on the screen, so that the next frame is drawn in full.
+    /// Needed whenever something else may have drawn to the terminal, such as a resize.
+    pub fn invalidate(&mut self) {
+        self.front.clear();
+        self.cursor = None;
+    }
+
+    /// Writes the cells of the frame which have changed since the last one.
+    /// With synchronized set, terminals which support it show the frame all at once.
+    pub fn present(&mut self, synchronized: bool, res: &mut UIResources) {
+        let synchronized = synchronized && self.supports_synchronized_output;
+        if synchronized {
+            res.eat(err!(queue!(self.out, BeginSynchronizedUpdate)));
+        }
+        let (draws, cursor) = self.changes();
+        for draw in draws {
+            match draw {
+                Draw::Clear => {
+                    // Clearing fills with the current background
+                    self.set_style(BLANK.1, res);
+                    res.eat(err!(queue!(self.out, Clear(ClearType::All))));
+                },
+                Draw::MoveTo(x, y) => self.move_to((x, y), res),
+                Draw::MoveRight(n) => {res.eat(err!(queue!(self.out, MoveRight(n))));},
+                Draw::Print(ch, style) => {
+                    self.set_style(style, res);
+                    self.wchar(ch, res);
+                },
+            }
+        }
+        if synchronized {
+            res.eat(err!(queue!(self.out, EndSynchronizedUpdate)));
+        }
+        self.flip(cursor);
+    }
+
+    /// What present would write for the frame drawn so far
+    fn changes(&self) -> (Vec<Draw>, Option<(u16, u16)>) {
+        diff(&self.front, &self.back, self.frame.0, self.cursor)
+    }
+
+    /// The frame is now what is on the screen
+    fn flip(&mut self, cursor: Option<(u16, u16)>) {
+        self.cursor = cursor;
+        std::mem::swap(&mut self.front, &mut self.back);
+    }
     
+    /// Changes how many colors are used, and redraws everything with them
+    pub fn set_colors(&mut self, colors: ColorDepth) {
+        self.colors = colors;
+        self.ansi.clear();
+        self.invalidate();
+    }
+
+    fn ansi(&mut self, color: Rgb) -> u8 {
+        let depth = self.colors;
+        *self.ansi.entry(color).or_insert_with(|| color.to_ansi(depth))
+    }
+
+    fn color(&mut self, color: Rgb) -> style::Color {
+        match self.colors {
+            ColorDepth::TrueColor => color.into(),
+            _ => style::Color::AnsiValue(self.ansi(color))
+        }
+    }
+
+    /// The style as it can be shown with the colors of the terminal.
+    /// Colors are replaced by those of the palette, so that styles which
+    /// look the same compare equal.
+    fn downsample(&mut self, style: Style) -> Style {
+        match self.colors {
+            ColorDepth::TrueColor => style,
+            ColorDepth::Monochrome => {
+                // Unset colors are taken to be light text on a dark background
+                let fg = style.fg.map_or(0.8, Rgb::lightness);
+                let bg = style.bg.map_or(0., Rgb::lightness);
+                Style {
+                    fg: None,
+                    bg: None,
+                    bold: style.bold || fg.max(bg) > 0.9,
+                    ital: style.ital,
+                    reverse: style.reverse != (bg > fg),
+                }
+            },
+            _ => Style {
+                fg: style.fg.map(|fg| ansi_color(self.ansi(fg))),
+                bg: style.bg.map(|bg| ansi_color(self.ansi(bg))),
+                ..style
+            }
+        }
+    }
+
     pub fn set_style(&mut self, style: Style, res: &mut UIResources) {
+        let style = self.downsample(style);
         if style == self.cur_style {
             return;
         }
+        let cur = self.cur_style;
+        // Colors can be changed without a reset, unless one is being removed
+        if style.bold == cur.bold && style.ital == cur.ital && style.reverse == cur.reverse
+        && (style.fg.is_some() || cur.fg.is_none()) && (style.bg.is_some() || cur.bg.is_none()) {
+            if let Some(fg) = style.fg.filter(|fg| Some(*fg) != cur.fg) {
+                let fg = self.color(fg);
+                res.eat(err!(queue!(self.out, style::SetForegroundColor(fg))));
+            }
+            if let Some(bg) = style.bg.filter(|bg| Some(*bg) != cur.bg) {
+                let bg = self.color(bg);
+                res.eat(err!(queue!(self.out, style::SetBackgroundColor(bg))));
+            }
+            self.cur_style = style;
+            return;
+        }
         res.eat(err!(queue!(self.out, style::SetAttribute(style::Attribute::Reset))));
         if style.ital {
             res.eat(err!(queue!(self.out, style::SetAttribute(style::Attribute::Italic))));
@@ -110,15 +307,17 @@ impl Terminal {
             res.eat(err!(queue!(self.out, style::SetAttribute(style::Attribute::Reverse))));
         }
         if let Some(fg) = style.fg {
+            let fg = self.color(fg);
             res.eat(err!(queue!(
                 self.out,
-                style::SetForegroundColor(fg.into()),
+                style::SetForegroundColor(fg),
             )));
         }
         if let Some(bg) = style.bg {
+            let bg = self.color(bg);
             res.eat(err!(queue!(
                 self.out,
-                style::SetBackgroundColor(bg.into()),
+                style::SetBackgroundColor(bg),
             )));
         }
         self.cur_style = style;
@@ -126,6 +325,7 @@ impl Terminal {
 
     pub fn move_to(&mut self, coord: (u16, u16), res: &mut UIResources) {
         res.eat(err!(queue!(self.out, MoveTo(coord.0, coord.1))));
+        self.cursor = Some(coord);
     }
     
     pub fn hide_cursor(&mut self, res: &mut UIResources) {
@@ -155,3 +355,104 @@ impl Terminal {
         res.eat(err!(disable_raw_mode()));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal() -> Terminal {
+        Terminal {
+            out: io::stdout(),
+            cur_style: BLANK.1,
+            supports_keyboard_enhancement: false,
+            supports_synchronized_output: false,
+            front: Vec::new(),
+            back: Vec::new(),
+            frame: (0, 0),
+            cursor: None,
+            colors: ColorDepth::TrueColor,
+            ansi: HashMap::new(),
+        }
+    }
+
+    /// Draws a frame of the given lines and takes it as presented
+    fn frame(term: &mut Terminal, lines: &[&str]) -> Vec<Draw> {
+        term.begin_frame(lines[0].len() as u16, lines.len() as u16);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, ch) in line.chars().enumerate() {
+                term.put((x as u16, y as u16), ch, Style::default());
+            }
+        }
+        let (draws, cursor) = term.changes();
+        term.flip(cursor);
+        draws
+    }
+
+    fn prints(draws: &[Draw]) -> usize {
+        draws.iter().filter(|draw| matches!(draw, Draw::Print(..))).count()
+    }
+
+    #[test]
+    fn first_frame_is_drawn_in_full() {
+        let mut term = terminal();
+        let draws = frame(&mut term, &["ab", "cd"]);
+        assert_eq!(draws[0], Draw::Clear);
+        assert_eq!(prints(&draws), 4);
+    }
+
+    #[test]
+    fn unchanged_frame_draws_nothing() {
+        let mut term = terminal();
+        frame(&mut term, &["ab", "cd"]);
+        assert!(frame(&mut term, &["ab", "cd"]).is_empty());
+    }
+
+    #[test]
+    fn one_changed_cell_is_one_move_and_one_print() {
+        let mut term = terminal();
+        frame(&mut term, &["abc", "def"]);
+        let draws = frame(&mut term, &["abc", "dxf"]);
+        assert_eq!(draws.len(), 2);
+        assert!(matches!(draws[0], Draw::MoveTo(1, 1) | Draw::MoveRight(_)));
+        assert_eq!(draws[1], Draw::Print('x', Style::default()));
+    }
+
+    #[test]
+    fn cells_next_to_each_other_need_one_move() {
+        let mut term = terminal();
+        frame(&mut term, &["abcd"]);
+        let draws = frame(&mut term, &["axyd"]);
+        assert_eq!(draws, vec![
+            Draw::MoveTo(1, 0),
+            Draw::Print('x', Style::default()),
+            Draw::Print('y', Style::default()),
+        ]);
+    }
+
+    #[test]
+    fn cells_further_along_a_line_move_right() {
+        let mut term = terminal();
+        frame(&mut term, &["abcde"]);
+        let draws = frame(&mut term, &["xbcye"]);
+        assert_eq!(draws[2], Draw::MoveRight(2));
+    }
+
+    #[test]
+    fn resize_draws_in_full() {
+        let mut term = terminal();
+        frame(&mut term, &["ab", "cd"]);
+        let draws = frame(&mut term, &["abc", "def"]);
+        assert_eq!(draws[0], Draw::Clear);
+        assert_eq!(prints(&draws), 6);
+    }
+
+    #[test]
+    fn invalidate_draws_in_full() {
+        let mut term = terminal();
+        frame(&mut term, &["ab", "cd"]);
+        term.invalidate();
+        let draws = frame(&mut term, &["ab", "cd"]);
+        assert_eq!(draws[0], Draw::Clear);
+        assert_eq!(prints(&draws), 4);
+    }
+}
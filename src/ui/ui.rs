@@ -1,4 +1,4 @@
-use crate::common::{UIResources, ExtTree, RecTree, Tree, UITile, Array2D, Style, ItrResult, Id, ReverseExtract, RemoveVec, TakeBox};
+use crate::common::{UIResources, ExtTree, RecTree, Tree, UITile, Array2D, Style, ItrResult, Id, ReverseExtract, RemoveVec, TakeBox, ColorDepth};
 use crate::ui::Terminal;
 use crate::Rgb;
 use std::collections::HashSet;
@@ -8,7 +8,7 @@ use super::widgets::{AddChild, IsWidget, Padding, RemoveChild};
 use super::{WidgetEnum, Widget};
 use std::time::{SystemTime, Duration};
 
-#[derive(Eq, PartialEq, Clone, Copy, Hash)]
+#[derive(Eq, PartialEq, Clone, Copy, Hash, Debug)]
 pub struct RawKey {
     pub code: KeyCode,
     pub modifiers: KeyModifiers,
@@ -34,6 +34,8 @@ pub enum Candidate {
     RightClick,
     Character,
     FinishAnimation,
+    /// Any key press at all, before it is translated into a candidate
+    AnyKey,
 }
 
 // Couples a key with a window id
@@ -50,6 +52,7 @@ pub enum Match {
     Character(char),
     Paste(String),
     FinishAnimation,
+    Key(RawKey),
 }
 pub type PollResult = (Id, Match);
 
@@ -72,6 +75,11 @@ fn match_poll(poll: &Poll, event: &Event, translation: Option<Candidate>, my_id:
         }
     }
     // Handling special matches
+    if let Event::Key(KeyEvent {code, modifiers, kind: KeyEventKind::Press, ..}) = event {
+        if poll.contains(&(my_id, Candidate::AnyKey)) {
+            return Some((my_id, Match::Key(RawKey {code: *code, modifiers: *modifiers})));
+        }
+    }
     match event {
         Event::Mouse(MouseEvent {kind: MouseEventKind::Down(MouseButton::Left), row, column, ..}) => {
             if poll.contains(&(my_id, Candidate::LeftClick)) {
@@ -532,6 +540,8 @@ impl UI {
             if let Event::Resize(x, y) = event {
                 self.width = x;
                 self.height = y;
+                // Terminals may move or clear what was on screen when resized
+                self.term.invalidate();
                 self.change_descend(root, self.fullscreen());
                 self.check_animate(root);
                 self.to_terminal(res);
@@ -553,13 +563,15 @@ impl UI {
     fn to_terminal(&mut self, res: &mut UIResources) {
         let widgets = &self.widgets[self.context];
         let WidgetData{ref buffer, ..} = widgets[widgets.root()];
-        for y in 0..buffer.area.height().min(self.height as usize) {
-            self.term.move_to((0,y as u16), res);
-            for x in 0..buffer.area.width().min(self.width as usize) {
+        let width = buffer.area.width().min(self.width as usize);
+        let height = buffer.area.height().min(self.height as usize);
+        self.term.begin_frame(width as u16, height as u16);
+        for y in 0..height {
+            for x in 0..width {
+                let coord = (x as u16, y as u16);
                 match buffer.area[(x,y)] {
                     BufferItem::Is(ch, style) => {
-                        self.term.set_style(style, res);
-                        self.term.wchar(ch, res);
+                        self.term.put(coord, ch, style);
                     },
                     BufferItem::References(id, x, y) => {
                         let x = x as usize;
@@ -567,34 +579,32 @@ impl UI {
                         if widgets.has(id) {
                             match widgets[id].buffer.area.get((x,y)) {
                                 Some(BufferItem::Is(ch, style)) => {
-                                    self.term.set_style(*style, res);
-                                    self.term.wchar(*ch, res);
+                                    self.term.put(coord, *ch, *style);
                                 },
                                 Some(BufferItem::References(..)) => {
                                     unreachable!();
                                 },
                                 None => {
-                                    self.term.set_style(Style {
+                                    self.term.put(coord, ' ', Style {
                                         bg: Some(Rgb(255,0,255)),
                                         fg: Some(Rgb(0,0,0)),
                                         bold: false, ital: false, reverse: false
-                                    }, res);
-                                    self.term.wchar(' ', res);
+                                    });
                                 }
                             }
                         } else {
-                            self.term.set_style(Style {
+                            self.term.put(coord, ' ', Style {
                                 bg: Some(Rgb(255,0,0)),
                                 fg: Some(Rgb(0,0,0)),
                                 bold: false, ital: false, reverse: false
-                            }, res);
-                            self.term.wchar(' ', res);
+                            });
                         }
                         
                     }
                 }
             }
         }
+        self.term.present(res.options.synchronized_output, res);
         // Windows terminals unhide the cursor whenever the terminal
         // is resized. Fun!
         self.term.hide_cursor(res);
@@ -623,6 +633,11 @@ impl UI {
         } else {None}
     }
     
+    /// Takes effect the next time the UI is drawn
+    pub fn set_colors(&mut self, colors: ColorDepth) {
+        self.term.set_colors(colors);
+    }
+
     pub fn stop(&mut self, res: &mut UIResources) {
         self.term.stop(res);
     }
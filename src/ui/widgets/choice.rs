@@ -16,6 +16,11 @@ impl Choice {
         }
     }
 
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        self.lines = lines;
+        self.selected = self.selected.min(self.lines.len().saturating_sub(1));
+    }
+
     pub fn shift_left(&mut self) {
         if self.selected > 0 {
             self.selected -= 1;
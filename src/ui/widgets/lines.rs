@@ -37,6 +37,13 @@ impl Lines {
     pub fn add_line(&mut self, st: String) {
         self.lines.push(st);
     }
+
+    /// Replaces every line, moving the selection back to the top.
+    pub fn set_lines(&mut self, vec: Vec<String>) {
+        self.lines = vec;
+        self.selected = 0;
+        self.current_first_line = 0;
+    }
 }
 
 impl Widget for Lines {
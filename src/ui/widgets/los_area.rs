@@ -1,5 +1,6 @@
 use crate::common::{Array2D, UITile, BLANKTILE, FILLEDTILE};
-use crate::game::{GameData, Traverser, los_scan, transform_uitile};
+use crate::game::{GameData, Traverser, MapID, los_scan, transform_uitile};
+use std::collections::HashSet;
 use super::widget_package::*;
 
 #[derive(Deserialize,Serialize)]
@@ -16,12 +17,33 @@ impl LOSArea {
         }
     }
 
-    pub fn set(&mut self, center: Traverser, data: &mut GameData) {
+    /// Scans out from the center; with a radius, nothing further away is shown.
+    pub fn set(&mut self, center: Traverser, radius: Option<usize>, data: &mut GameData) {
         los_scan(&mut self.t_arr, center,data);
+        if let Some(radius) = radius {
+            let (cx, cy) = self.t_arr.center();
+            for c in self.t_arr.coord_iter() {
+                let (dx, dy) = (c.0.abs_diff(cx), c.1.abs_diff(cy));
+                if dx*dx+dy*dy > radius*radius {
+                    self.t_arr[c] = None;
+                }
+            }
+        }
         transform_uitile(&mut self.ui_arr, &self.t_arr, data);
     }
     
     pub fn dim(&self) -> (usize,usize) {self.t_arr.dim()}
+
+    /// Every map with a tile in the current line of sight.
+    pub fn visible_maps(&self) -> HashSet<MapID> {
+        let mut maps = HashSet::new();
+        for c in self.t_arr.coord_iter() {
+            if let Some(trav) = self.t_arr[c] {
+                maps.insert(trav.tile.map);
+            }
+        }
+        maps
+    }
 }
 
 impl Widget for LOSArea {
@@ -20,6 +20,13 @@ pub struct TitleTop {
     title_alignment: (f64,f64),
     hash: PersistentHash,
     step: usize,
+    #[serde(skip, default = "title_background")]
+    background: Vec<u8>,
+}
+
+/// Stored one character per byte
+fn title_background() -> Vec<u8> {
+    crate::tr!("title_background").chars().map(|ch| ch as u32 as u8).collect()
 }
 
 impl TitleTop {
@@ -31,6 +38,7 @@ impl TitleTop {
         Self {
             hash: PersistentHash::new(),
             step: BELOW_STEP,
+            background: title_background(),
             title_alignment,
             title_image
         }
@@ -39,7 +47,6 @@ impl TitleTop {
     pub fn hash(&self) -> PersistentHash {self.hash}
     pub fn step(&self) -> usize {self.step}
 
-    const BACKGROUND: &'static [u8] = &crate::translate!(title_background);
     const STREAK_BITS: usize = 2;
 
     fn draw_background(&self, buffer: &mut WidgetBuffer) {
@@ -74,7 +81,7 @@ impl TitleTop {
     fn wbackground(&self, x: usize, y: usize, bound: WidgetBound, buffer: &mut WidgetBuffer) {
         let exact = x*bound.height as usize+y;
         let (ch, offset) = (exact/8, (8-1)-(exact%8));
-        let here = if ch >= Self::BACKGROUND.len() {'0'} else {('0' as u8 | ((Self::BACKGROUND[ch]>>offset)&1)) as char};
+        let here = if ch >= self.background.len() {'0'} else {('0' as u8 | ((self.background[ch]>>offset)&1)) as char};
         buffer.wchar_at((x,y), here, Style::default());
     }
 }